@@ -2,20 +2,20 @@
 
 TARGET_DIR=$1
 
-cargo rustc --release -- -C opt-level=3 -C target-feature=+avx2,+avx,+sse2,-avx512vl,-avx512f,-avx512bw,-avx512cd,-avx512dq,-avx512vnni --cfg width_10000
+RUSTFLAGS="-C opt-level=3 -C target-feature=+avx2,+avx,+sse2,-avx512vl,-avx512f,-avx512bw,-avx512cd,-avx512dq,-avx512vnni --cfg width_10000" cargo build --release
 cp ../target/release/lgca "${TARGET_DIR}/lgca-10000-avx2"
 
-cargo rustc --release -- -C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_10000 --cfg use_real_collisions_in_core
+RUSTFLAGS="-C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_10000 --cfg use_real_collisions_in_core" cargo build --release
 cp ../target/release/lgca "${TARGET_DIR}/lgca-10000-real"
 
-cargo rustc --release -- -C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_100
+RUSTFLAGS="-C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_100" cargo build --release
 cp ../target/release/lgca "${TARGET_DIR}/lgca-100"
 
-cargo rustc --release -- -C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_1000
+RUSTFLAGS="-C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_1000" cargo build --release
 cp ../target/release/lgca "${TARGET_DIR}/lgca-1000"
 
-cargo rustc --release -- -C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_10000
+RUSTFLAGS="-C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_10000" cargo build --release
 cp ../target/release/lgca "${TARGET_DIR}/lgca-10000"
 
-cargo rustc --release -- -C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_100000
+RUSTFLAGS="-C opt-level=3 -C target-feature=+avx2,+avx,+sse2,+avx512vl,+avx512f,+avx512bw,+avx512cd,+avx512dq,+avx512vnni --cfg width_100000" cargo build --release
 cp ../target/release/lgca "${TARGET_DIR}/lgca-100000"
@@ -0,0 +1,6 @@
+#!/usr/bin/env bash
+# Regenerate the C header for the interface in src/lgca/ffi.rs
+# Needs cbindgen: cargo install cbindgen
+
+cd "$(dirname "$0")"
+cbindgen --config cbindgen.toml --crate lgca --output include/lgca.h
@@ -0,0 +1,98 @@
+#ifndef LGCA_H
+#define LGCA_H
+
+/* Generated by ./generate_header.sh from src/lgca/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A null pointer or an invalid argument was passed
+#define LGCA_ERROR_ARGUMENT -1
+
+// The scenario string could not be parsed
+#define LGCA_ERROR_SCENARIO -2
+
+// The output buffers are too small
+#define LGCA_ERROR_BUFFER_SIZE -3
+
+// The simulation panicked. The state of the simulation is unknown, it should only be destroyed
+#define LGCA_ERROR_PANIC -4
+
+// Opaque handle to a simulation without MPI
+typedef struct LgcaSimulation LgcaSimulation;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Number of cells in a row, fixed when the library was compiled
+uintptr_t lgca_width(void);
+
+// Create an empty simulation with `height` rows.
+//
+// The height must be at least 2, otherwise null is returned. Null is also returned if the size of the grid
+// overflows. Running out of memory aborts the process like any other failed allocation in Rust.
+LgcaSimulation *lgca_create(uintptr_t height);
+
+// Reset the particles, the round counter and the timings and set the simulation up from a scenario string
+// like `box=500 noise=0.04 body_force=0.001`
+//
+// # Safety
+// `simulation` must come from `lgca_create` and `scenario` must be a null terminated string.
+int lgca_configure(LgcaSimulation *simulation, const char *scenario);
+
+// Calculate `rounds` rounds
+//
+// # Safety
+// `simulation` must come from `lgca_create`.
+int lgca_step(LgcaSimulation *simulation, uintptr_t rounds);
+
+// Number of rounds calculated so far
+//
+// # Safety
+// `simulation` must come from `lgca_create`.
+uintptr_t lgca_round(const LgcaSimulation *simulation);
+
+// Number of particles in the grid
+//
+// # Safety
+// `simulation` must come from `lgca_create`.
+uint64_t lgca_particles(const LgcaSimulation *simulation);
+
+// Write the number of blocks in a row and the number of rows of blocks for `block_size`
+//
+// # Safety
+// `simulation` must come from `lgca_create`, `width` and `height` must be valid pointers.
+int lgca_fields_size(const LgcaSimulation *simulation,
+                     uintptr_t block_size,
+                     uintptr_t *width,
+                     uintptr_t *height);
+
+// Write the density and velocity averaged over blocks of `block_size` cells, row by row.
+//
+// Every buffer must hold `length` floats, at least as many as there are blocks.
+// Returns the number of blocks written. If that does not fit into an int, nothing is written and
+// `LGCA_ERROR_ARGUMENT` is returned, a larger `block_size` helps.
+//
+// # Safety
+// `simulation` must come from `lgca_create`, the buffers must be valid for `length` floats.
+int lgca_read_fields(const LgcaSimulation *simulation,
+                     uintptr_t block_size,
+                     float *density,
+                     float *velocity_x,
+                     float *velocity_y,
+                     uintptr_t length);
+
+// Free a simulation. Passing null does nothing
+//
+// # Safety
+// `simulation` must come from `lgca_create` and must not be used afterwards.
+void lgca_destroy(LgcaSimulation *simulation);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LGCA_H */
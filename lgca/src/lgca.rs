@@ -1,5 +1,32 @@
+pub mod boundary;
 pub mod cell;
+pub mod ensemble;
+pub mod experiments;
+pub mod ffi;
+pub mod fields;
+pub mod forcing;
+pub mod init_image;
+pub mod io_rank;
+pub mod lattice;
+pub mod memory;
 pub mod new_movements;
+pub mod obstacles;
+pub mod output;
+pub mod periodic;
+pub mod porous;
+pub mod preview;
+pub mod probes;
+pub mod reversal;
+pub mod scenario;
+pub mod simulation;
+pub mod snapshot;
+pub mod statistics;
+pub mod species;
+pub mod terminal;
+#[cfg(test)]
+pub mod testing;
+pub mod tiling;
+pub mod tracers;
 pub mod visualization;
 
 pub use cell::Cell;
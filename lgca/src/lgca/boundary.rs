@@ -0,0 +1,686 @@
+use rand::prelude::*;
+use std::str::FromStr;
+
+use super::{
+    cell::RNG,
+    lattice::{Fhp, LatticeModel, FHP_EVEN_ROW_OFFSETS, FHP_ODD_ROW_OFFSETS},
+    new_movements::{movement_bottom_even_row, movement_bottom_row, movement_top_row},
+    Cell,
+};
+
+/// Rules for the rows at the top and bottom of the whole grid.
+///
+/// Rows between ranks are not affected, they are calculated with the rows received from the neighbors.
+/// Without `collide` the particles only move, like with the `COLLIDE` parameter of the kernels.
+pub trait Boundary<const WIDTH: usize>: Send + Sync {
+    /// Calculate the first row of the grid. It is always an even row
+    fn top_row(
+        &self,
+        current: &[Cell; WIDTH],
+        below: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        collide: bool,
+    );
+
+    /// Calculate the last row of the grid. It is even if the grid has an odd number of rows
+    fn bottom_row(
+        &self,
+        above: &[Cell; WIDTH],
+        current: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        even: bool,
+        collide: bool,
+    );
+
+    /// Fix the first and last cell of a row between the top and bottom row.
+    ///
+    /// The kernels already calculated the whole row and reflect particles at its ends.
+    fn row_ends(
+        &self,
+        _even: bool,
+        _above: &[Cell; WIDTH],
+        _current: &[Cell; WIDTH],
+        _below: &[Cell; WIDTH],
+        _result: &mut [Cell; WIDTH],
+        _collide: bool,
+    ) {
+    }
+
+    /// Channel a single particle in `channel` of cell `x` is sent into, if its neighbor in that direction lies beyond
+    /// an edge. The particle stays in its cell, zero means it left the grid.
+    /// `top` and `bottom` tell whether the row is the first or last row of the grid
+    fn edge_channel(&self, x: usize, even: bool, top: bool, bottom: bool, channel: u8) -> u8;
+
+    /// Whether the top and bottom row are neighbors. Then the simulation exchanges them like the rows between ranks
+    /// and never calls `top_row` and `bottom_row`
+    fn periodic_rows(&self) -> bool {
+        false
+    }
+
+    /// Whether the first and last cell of a row are neighbors
+    fn periodic_columns(&self) -> bool {
+        false
+    }
+
+    /// Whether moving particles without collisions always gives the same result.
+    /// Only then a second bit plane like the colors of two species can follow the particles
+    fn deterministic(&self) -> bool {
+        true
+    }
+}
+
+/// Particles bounce off the walls like light off a mirror
+pub struct ReflectingBoundary;
+
+impl<const WIDTH: usize> Boundary<WIDTH> for ReflectingBoundary
+where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    fn top_row(
+        &self,
+        current: &[Cell; WIDTH],
+        below: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        collide: bool,
+    ) {
+        if collide {
+            movement_top_row::<WIDTH, true>(current, below, result);
+        } else {
+            movement_top_row::<WIDTH, false>(current, below, result);
+        }
+    }
+
+    fn bottom_row(
+        &self,
+        above: &[Cell; WIDTH],
+        current: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        even: bool,
+        collide: bool,
+    ) {
+        match (even, collide) {
+            (true, true) => movement_bottom_even_row::<WIDTH, true>(above, current, result),
+            (true, false) => movement_bottom_even_row::<WIDTH, false>(above, current, result),
+            (false, true) => movement_bottom_row::<WIDTH, true>(above, current, result),
+            (false, false) => movement_bottom_row::<WIDTH, false>(above, current, result),
+        }
+    }
+
+    fn edge_channel(&self, x: usize, even: bool, top: bool, bottom: bool, channel: u8) -> u8 {
+        // The kernels reflect particles exactly like reflecting edges
+        let reflecting = EdgeBoundary::new(
+            Edge::Reflecting,
+            Edge::Reflecting,
+            Edge::Reflecting,
+            Edge::Reflecting,
+        );
+        Boundary::<WIDTH>::edge_channel(&reflecting, x, even, top, bottom, channel)
+    }
+}
+
+/// What happens to particles at one edge of the grid
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edge {
+    /// Particles get mirrored like light, the same as with `ReflectingBoundary`
+    Reflecting,
+    /// Particles go back where they came from, so the flow sticks to the wall
+    BounceBack,
+    /// Particles leave on one side and come back on the opposite one, which also has to be periodic
+    Periodic,
+    /// Particles leave the grid. The cells at the edge receive the particles they send into the grid themselves
+    Open,
+    /// A wall that moves along the edge with the given speed, to the east at the north and south edge and to the south
+    /// at the west and east edge. Particles bounce back or get mirrored, whatever moves them more like the wall
+    MovingWall(f64),
+}
+
+impl FromStr for Edge {
+    type Err = String;
+
+    /// Parse `reflecting`, `bounce-back`, `periodic`, `open` or `moving:<speed>`
+    fn from_str(description: &str) -> Result<Self, Self::Err> {
+        match description {
+            "reflecting" => Ok(Edge::Reflecting),
+            "bounce-back" => Ok(Edge::BounceBack),
+            "periodic" => Ok(Edge::Periodic),
+            "open" => Ok(Edge::Open),
+            _ => match description.split_once(':') {
+                Some(("moving", speed)) => speed
+                    .parse()
+                    .map(Edge::MovingWall)
+                    .map_err(|_| format!("The speed of a moving wall must be a number, got {}", speed)),
+                _ => Err(format!(
+                    "Unknown edge {}, expected reflecting, bounce-back, periodic, open or moving:<speed>",
+                    description
+                )),
+            },
+        }
+    }
+}
+
+const NORTH: u8 = 1;
+const SOUTH: u8 = 2;
+const WEST: u8 = 4;
+const EAST: u8 = 8;
+
+/// Every edge of the grid with its own rules.
+///
+/// The kernels calculate the rows as usual, then the cells at the edges are calculated again cell by cell.
+/// A particle that would leave through a single edge is handled by the rules of that edge.
+/// In corners where a particle would leave through two edges at once, it bounces back, or leaves if one of them is open.
+/// The cells collide with `process_collision`, like the border cells of the kernels.
+pub struct EdgeBoundary {
+    pub north: Edge,
+    pub south: Edge,
+    pub west: Edge,
+    pub east: Edge,
+}
+
+impl EdgeBoundary {
+    pub fn new(north: Edge, south: Edge, west: Edge, east: Edge) -> Self {
+        assert!(
+            (north == Edge::Periodic) == (south == Edge::Periodic),
+            "The north and south edge can only be periodic together"
+        );
+        assert!(
+            (west == Edge::Periodic) == (east == Edge::Periodic),
+            "The west and east edge can only be periodic together"
+        );
+        Self {
+            north,
+            south,
+            west,
+            east,
+        }
+    }
+
+    /// Edges the neighbor of cell `x` in every direction is beyond, zero for neighbors in the grid.
+    /// `top` and `bottom` tell whether the row is the first or last row of the grid
+    fn outside<const WIDTH: usize>(
+        &self,
+        x: usize,
+        even: bool,
+        top: bool,
+        bottom: bool,
+    ) -> [u8; 6] {
+        let offsets = if even {
+            FHP_EVEN_ROW_OFFSETS
+        } else {
+            FHP_ODD_ROW_OFFSETS
+        };
+        let mut outside = [0u8; 6];
+        for (direction, (offset_x, offset_y)) in offsets.iter().enumerate() {
+            let neighbor_x = x as isize + offset_x;
+            if neighbor_x < 0 && self.west != Edge::Periodic {
+                outside[direction] |= WEST;
+            }
+            if neighbor_x >= WIDTH as isize && self.east != Edge::Periodic {
+                outside[direction] |= EAST;
+            }
+            if *offset_y < 0 && top {
+                outside[direction] |= NORTH;
+            }
+            if *offset_y > 0 && bottom {
+                outside[direction] |= SOUTH;
+            }
+        }
+        outside
+    }
+
+    /// Particles that the edges in `outside` send back into a cell with the particles `own`
+    fn edge_particles(&self, own: u8, outside: &[u8; 6]) -> u8 {
+        let bit = |direction: usize| Fhp::DIRECTIONS[direction].bit;
+        let opposite = |direction: usize| Fhp::DIRECTIONS[direction].opposite;
+        let mut raw = 0;
+        for (side, edge) in [
+            (NORTH, self.north),
+            (SOUTH, self.south),
+            (WEST, self.west),
+            (EAST, self.east),
+        ] {
+            // Directions that leave through this edge alone, as bits of their indices
+            let leaving_mask = (0..6)
+                .filter(|d| outside[*d] == side)
+                .fold(0u8, |mask, d| mask | (1 << d));
+            if leaving_mask == 0 {
+                continue;
+            }
+            let leaving = || (0..6).filter(move |d| leaving_mask & (1 << d) != 0);
+            let mirror = |direction: usize| {
+                if side & (NORTH | SOUTH) != 0 {
+                    (6 - direction) % 6
+                } else {
+                    (9 - direction) % 6
+                }
+            };
+            // Mirroring only works if every mirrored direction is fed by this edge alone
+            let can_mirror =
+                leaving().all(|direction| outside[opposite(mirror(direction))] == side);
+            let present = || leaving().filter(|direction| own & bit(*direction) != 0);
+            let bounced = present().fold(0, |raw, d| raw | bit(opposite(d)));
+            let mirrored = present().fold(0, |raw, d| raw | bit(mirror(d)));
+
+            raw |= match edge {
+                Edge::Periodic => panic!("Periodic rows have to be exchanged by the simulation"),
+                Edge::Open => leaving().fold(0, |raw, d| raw | (own & bit(opposite(d)))),
+                Edge::BounceBack => bounced,
+                Edge::Reflecting if can_mirror => mirrored,
+                Edge::Reflecting => bounced,
+                Edge::MovingWall(speed) if can_mirror && bounced != mirrored => {
+                    // Pick the state with more momentum along the wall with a probability that gives the particles
+                    // the speed of the wall on average
+                    let tangential = |raw: u8| -> f64 {
+                        Fhp::DIRECTIONS
+                            .iter()
+                            .filter(|direction| raw & direction.bit != 0)
+                            .map(|direction| {
+                                if side & (NORTH | SOUTH) != 0 {
+                                    direction.velocity.0
+                                } else {
+                                    direction.velocity.1
+                                }
+                            })
+                            .sum()
+                    };
+                    let (slow, fast) = if tangential(bounced) < tangential(mirrored) {
+                        (bounced, mirrored)
+                    } else {
+                        (mirrored, bounced)
+                    };
+                    let target = speed * bounced.count_ones() as f64;
+                    let probability = ((target - tangential(slow))
+                        / (tangential(fast) - tangential(slow)))
+                    .clamp(0.0, 1.0);
+                    if RNG.with(|random| random.borrow_mut().gen_bool(probability)) {
+                        fast
+                    } else {
+                        slow
+                    }
+                }
+                Edge::MovingWall(_) => bounced,
+            };
+        }
+
+        for direction in (0..6).filter(|d| outside[*d].count_ones() == 2) {
+            let open = [
+                (NORTH, self.north),
+                (SOUTH, self.south),
+                (WEST, self.west),
+                (EAST, self.east),
+            ]
+            .iter()
+            .any(|(side, edge)| outside[direction] & side != 0 && *edge == Edge::Open);
+            if open {
+                raw |= own & bit(opposite(direction));
+            } else if own & bit(direction) != 0 {
+                raw |= bit(opposite(direction));
+            }
+        }
+
+        raw
+    }
+
+    /// Calculate cell `x` of a row cell by cell. The row above or below is `None` outside of the grid
+    fn cell<const WIDTH: usize>(
+        &self,
+        x: usize,
+        even: bool,
+        above: Option<&[Cell; WIDTH]>,
+        current: &[Cell; WIDTH],
+        below: Option<&[Cell; WIDTH]>,
+        collide: bool,
+    ) -> Cell {
+        let outside = self.outside::<WIDTH>(x, even, above.is_none(), below.is_none());
+        let mut raw = 0;
+        let offsets = if even {
+            FHP_EVEN_ROW_OFFSETS
+        } else {
+            FHP_ODD_ROW_OFFSETS
+        };
+        for (direction, (offset_x, offset_y)) in offsets.iter().enumerate() {
+            let row = match offset_y {
+                -1 => above,
+                1 => below,
+                _ => Some(current),
+            };
+            if let (0, Some(row)) = (outside[direction], row) {
+                let neighbor = row[(x as isize + offset_x).rem_euclid(WIDTH as isize) as usize];
+                // The neighbor sends the particles moving in the opposite direction
+                raw |= neighbor.raw & Fhp::DIRECTIONS[Fhp::DIRECTIONS[direction].opposite].bit;
+            }
+        }
+        raw |= self.edge_particles(current[x].raw, &outside);
+
+        let mut cell = Cell { raw };
+        if collide {
+            cell.process_collision();
+        }
+        cell
+    }
+}
+
+impl<const WIDTH: usize> Boundary<WIDTH> for EdgeBoundary {
+    fn top_row(
+        &self,
+        current: &[Cell; WIDTH],
+        below: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        collide: bool,
+    ) {
+        for (x, cell) in result.iter_mut().enumerate() {
+            *cell = self.cell(x, true, None, current, Some(below), collide);
+        }
+    }
+
+    fn bottom_row(
+        &self,
+        above: &[Cell; WIDTH],
+        current: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        even: bool,
+        collide: bool,
+    ) {
+        for (x, cell) in result.iter_mut().enumerate() {
+            *cell = self.cell(x, even, Some(above), current, None, collide);
+        }
+    }
+
+    fn row_ends(
+        &self,
+        even: bool,
+        above: &[Cell; WIDTH],
+        current: &[Cell; WIDTH],
+        below: &[Cell; WIDTH],
+        result: &mut [Cell; WIDTH],
+        collide: bool,
+    ) {
+        result[0] = self.cell(0, even, Some(above), current, Some(below), collide);
+        result[WIDTH - 1] = self.cell(WIDTH - 1, even, Some(above), current, Some(below), collide);
+    }
+
+    fn edge_channel(&self, x: usize, even: bool, top: bool, bottom: bool, channel: u8) -> u8 {
+        self.edge_particles(channel, &self.outside::<WIDTH>(x, even, top, bottom))
+    }
+
+    fn periodic_rows(&self) -> bool {
+        self.north == Edge::Periodic
+    }
+
+    fn periodic_columns(&self) -> bool {
+        self.west == Edge::Periodic
+    }
+
+    fn deterministic(&self) -> bool {
+        ![self.north, self.south, self.west, self.east]
+            .iter()
+            .any(|edge| matches!(edge, Edge::MovingWall(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{
+        cell::{TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST, TO_WEST},
+        periodic::{propagate, Neighbors},
+        simulation::Simulation,
+    };
+    use std::collections::{HashMap, HashSet};
+
+    const WIDTH: usize = 6;
+
+    fn single_particle(
+        boundary: EdgeBoundary,
+        height: usize,
+        x: usize,
+        y: usize,
+        channel: u8,
+    ) -> Vec<[Cell; WIDTH]> {
+        let mut simulation = Simulation::<WIDTH>::new(height, None);
+        simulation.set_boundary(Box::new(boundary));
+        simulation.grid_mut()[y][x].raw = channel;
+        simulation.step();
+        simulation.grid().to_vec()
+    }
+
+    fn edges(north: Edge, south: Edge, west: Edge, east: Edge) -> EdgeBoundary {
+        EdgeBoundary::new(north, south, west, east)
+    }
+
+    #[test]
+    fn parse_edges() {
+        assert_eq!("bounce-back".parse(), Ok(Edge::BounceBack));
+        assert_eq!("moving:0.25".parse(), Ok(Edge::MovingWall(0.25)));
+        assert!("moving:fast".parse::<Edge>().is_err());
+        assert!("sticky".parse::<Edge>().is_err());
+    }
+
+    #[test]
+    fn reflecting_edges_match_the_kernels() {
+        for height in [6, 7] {
+            for y in 0..height {
+                for x in 0..WIDTH {
+                    for channel in 0..6 {
+                        let mut kernels = Simulation::<WIDTH>::new(height, None);
+                        kernels.grid_mut()[y][x].raw = 1 << channel;
+                        kernels.step();
+                        let reflecting = edges(
+                            Edge::Reflecting,
+                            Edge::Reflecting,
+                            Edge::Reflecting,
+                            Edge::Reflecting,
+                        );
+                        assert_eq!(
+                            single_particle(reflecting, height, x, y, 1 << channel),
+                            kernels.grid(),
+                            "Particle in channel {} of cell ({}, {})",
+                            channel,
+                            x,
+                            y
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn periodic_edges_match_the_periodic_grid() {
+        for in_place in [false, true] {
+            let mut simulation = Simulation::<WIDTH>::new(8, None);
+            simulation.set_boundary(Box::new(edges(
+                Edge::Periodic,
+                Edge::Periodic,
+                Edge::Periodic,
+                Edge::Periodic,
+            )));
+            simulation.set_in_place(in_place);
+            simulation.add_seeded_noise(0.4, 1);
+            let original = simulation.grid().to_vec();
+            simulation.propagate();
+            for (y, row) in simulation.grid().iter().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    let neighbors = Neighbors::of(x, y, WIDTH, 8);
+                    assert_eq!(cell.raw, propagate(&neighbors, |(x, y)| original[y][x].raw));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounce_back_returns_particles() {
+        let boundary = || {
+            edges(
+                Edge::BounceBack,
+                Edge::BounceBack,
+                Edge::BounceBack,
+                Edge::BounceBack,
+            )
+        };
+        let grid = single_particle(boundary(), 6, 2, 0, TO_NORTH_EAST);
+        assert_eq!(grid[0][2].raw, TO_SOUTH_WEST);
+        let grid = single_particle(boundary(), 6, 0, 3, TO_NORTH_WEST);
+        assert_eq!(grid[3][0].raw, TO_SOUTH_EAST);
+    }
+
+    #[test]
+    fn open_edges_lose_particles() {
+        let open = || edges(Edge::Open, Edge::Open, Edge::Open, Edge::Open);
+        let grid = single_particle(open(), 6, 0, 2, TO_WEST);
+        assert!(grid.iter().flatten().all(|cell| cell.raw == 0));
+        let grid = single_particle(open(), 6, 3, 5, TO_SOUTH_WEST);
+        assert!(grid.iter().flatten().all(|cell| cell.raw == 0));
+        // Particles moving into the grid are copied from the edge
+        let grid = single_particle(open(), 6, 3, 0, TO_SOUTH_EAST);
+        assert_eq!(grid[0][3].raw, TO_SOUTH_EAST);
+        assert_eq!(grid[1][4].raw, TO_SOUTH_EAST);
+    }
+
+    #[test]
+    fn fast_lids_drag_particles_along() {
+        let lid = |speed| {
+            edges(
+                Edge::MovingWall(speed),
+                Edge::BounceBack,
+                Edge::BounceBack,
+                Edge::BounceBack,
+            )
+        };
+        for channel in [TO_NORTH_EAST, TO_NORTH_WEST] {
+            assert_eq!(
+                single_particle(lid(0.5), 6, 2, 0, channel)[0][2].raw,
+                TO_SOUTH_EAST
+            );
+            assert_eq!(
+                single_particle(lid(-0.5), 6, 2, 0, channel)[0][2].raw,
+                TO_SOUTH_WEST
+            );
+        }
+    }
+
+    #[test]
+    fn edges_collide_in_both_directions() {
+        let bounce_back = || {
+            edges(
+                Edge::BounceBack,
+                Edge::BounceBack,
+                Edge::BounceBack,
+                Edge::BounceBack,
+            )
+        };
+        let mut outcomes = HashSet::new();
+        for _ in 0..64 {
+            let mut simulation = Simulation::<WIDTH>::new(6, None);
+            simulation.set_boundary(Box::new(bounce_back()));
+            simulation.grid_mut()[0][1].raw = TO_EAST;
+            simulation.grid_mut()[0][3].raw = TO_WEST;
+            simulation.step();
+            outcomes.insert(simulation.grid()[0][2].raw);
+        }
+        // Like the kernels, the wall does not prefer one chirality
+        assert_eq!(
+            outcomes,
+            HashSet::from([TO_NORTH_EAST | TO_SOUTH_WEST, TO_SOUTH_EAST | TO_NORTH_WEST])
+        );
+    }
+
+    #[test]
+    fn reflecting_edges_collide_like_the_kernels() {
+        // States that draw a random number in `process_collision`
+        let random = |raw: u8| {
+            matches!(
+                raw,
+                0b00001001 | 0b00010010 | 0b00100100 | 0b00011011 | 0b00101101 | 0b00110110
+            )
+        };
+        let particles = |height: usize| {
+            (0..height)
+                .flat_map(|y| (0..WIDTH).flat_map(move |x| (0..6).map(move |c| (y, x, 1 << c))))
+        };
+        // A single thread and a seeded RNG draw the same numbers for both boundaries as long as they collide the
+        // same cells in the same order
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let mut random_cells = 0;
+        for height in [6, 7] {
+            // Where every particle ends up after the movement
+            let mut destinations = HashMap::new();
+            for (y, x, channel) in particles(height) {
+                let mut simulation = Simulation::<WIDTH>::new(height, None);
+                simulation.grid_mut()[y][x].raw = channel;
+                simulation.propagate();
+                let (to_y, row) = simulation
+                    .grid()
+                    .iter()
+                    .enumerate()
+                    .find(|(_, row)| row.iter().any(|cell| cell.raw != 0))
+                    .unwrap();
+                let to_x = row.iter().position(|cell| cell.raw != 0).unwrap();
+                destinations.insert((y, x, channel), (to_y, to_x, row[to_x].raw));
+            }
+
+            for seed in 0..8 {
+                let mut noise = Simulation::<WIDTH>::new(height, None);
+                noise.add_seeded_noise(0.5, seed);
+                let mut grid = noise.grid().to_vec();
+                // Only the cores of the top and bottom row, which both boundaries collide from west to east,
+                // may end up in a random state
+                let mut moved = vec![0u8; WIDTH * height];
+                for (y, x, channel) in particles(height) {
+                    if grid[y][x].raw & channel == 0 {
+                        continue;
+                    }
+                    let (to_y, to_x, to_channel) = destinations[&(y, x, channel)];
+                    let state = moved[to_y * WIDTH + to_x] | to_channel;
+                    let edge_core =
+                        (to_y == 0 || to_y == height - 1) && to_x > 0 && to_x < WIDTH - 1;
+                    if random(state) && !edge_core {
+                        grid[y][x].raw &= !channel;
+                    } else {
+                        moved[to_y * WIDTH + to_x] = state;
+                    }
+                }
+                random_cells += moved.iter().filter(|&&raw| random(raw)).count();
+
+                let step = |boundary: Box<dyn Boundary<WIDTH>>| {
+                    pool.install(|| {
+                        let mut simulation = Simulation::<WIDTH>::new(height, None);
+                        simulation.set_boundary(boundary);
+                        simulation.grid_mut().copy_from_slice(&grid);
+                        RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+                        simulation.step();
+                        simulation.grid().to_vec()
+                    })
+                };
+                let reflecting = edges(
+                    Edge::Reflecting,
+                    Edge::Reflecting,
+                    Edge::Reflecting,
+                    Edge::Reflecting,
+                );
+                assert_eq!(
+                    step(Box::new(reflecting)),
+                    step(Box::new(ReflectingBoundary)),
+                    "Height {}, seed {}",
+                    height,
+                    seed
+                );
+            }
+        }
+        assert!(random_cells > 10, "{}", random_cells);
+    }
+
+    #[test]
+    #[should_panic]
+    fn periodic_edges_come_in_pairs() {
+        edges(
+            Edge::Periodic,
+            Edge::Reflecting,
+            Edge::Reflecting,
+            Edge::Reflecting,
+        );
+    }
+}
@@ -12,10 +12,21 @@ pub const TO_SOUTH_WEST: u8 = 0b00100000;
 use rand::prelude::*;
 
 #[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
 pub struct Cell {
     pub raw: u8,
 }
 
+/// View a row as raw bytes, for sending it with MPI
+pub fn as_bytes<const WIDTH: usize>(row: &[Cell; WIDTH]) -> &[u8; WIDTH] {
+    unsafe { &*(row as *const [Cell; WIDTH] as *const [u8; WIDTH]) }
+}
+
+/// View a row as raw bytes, for receiving it with MPI
+pub fn as_bytes_mut<const WIDTH: usize>(row: &mut [Cell; WIDTH]) -> &mut [u8; WIDTH] {
+    unsafe { &mut *(row as *mut [Cell; WIDTH] as *mut [u8; WIDTH]) }
+}
+
 impl Debug for Cell {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Cell")
@@ -101,7 +112,8 @@ impl Cell {
         }
     }
 
-    pub fn get_direction(&self) -> (f32, f32) {
+    /// Sum of the velocities of all particles. y points south
+    pub fn get_momentum(&self) -> (f32, f32) {
         let mut x: f32 = 0.0;
         let mut y: f32 = 0.0;
         if self.to_east() {
@@ -128,6 +140,11 @@ impl Cell {
             y -= 0.866;
         }
 
+        return (x, y);
+    }
+
+    pub fn get_direction(&self) -> (f32, f32) {
+        let (x, y) = self.get_momentum();
         let angle = y.atan2(x) / std::f32::consts::PI * 2.0;
         let length = (x * x + y * y).sqrt();
 
@@ -201,6 +218,91 @@ impl Cell {
         }
     }
     // end::collision_real[]
+
+    /// Same as the fake collisions in `movement_core`, but for a single cell.
+    /// `south_east`, `east` and `north_east` are the neighbors before the movement.
+    pub fn process_fake_collision(&mut self, south_east: &Cell, east: &Cell, north_east: &Cell) {
+        let south_east = south_east.raw & TO_SOUTH_EAST != 0;
+        let east = east.raw & TO_EAST != 0;
+        let north_east = north_east.raw & TO_NORTH_EAST != 0;
+        self.raw = match self.raw {
+            0b00100100 if south_east => 0b00001001,
+            0b00100100 => 0b00010010,
+            0b00011011 if south_east => 0b00110110,
+            0b00011011 => 0b00101101,
+
+            0b00010010 if east => 0b00100100,
+            0b00010010 => 0b00001001,
+            0b00101101 if east => 0b00011011,
+            0b00101101 => 0b00110110,
+
+            0b00001001 if north_east => 0b00010010,
+            0b00001001 => 0b00100100,
+            0b00110110 if north_east => 0b00101101,
+            0b00110110 => 0b00011011,
+
+            0b00101010 => 0b00010101,
+            0b00010101 => 0b00101010,
+
+            _ => self.raw,
+        }
+    }
+
+    /// Rotate colliding particles by 60 degrees, clockwise if `chirality` is set.
+    ///
+    /// With a random chirality this behaves like `process_collision`, but it is a bijection:
+    /// colliding again with the opposite chirality restores the cell.
+    pub fn process_chiral_collision(&mut self, chirality: bool) {
+        if matches!(
+            self.raw,
+            0b00001001
+                | 0b00010010
+                | 0b00100100
+                | 0b00010101
+                | 0b00101010
+                | 0b00011011
+                | 0b00101101
+                | 0b00110110
+        ) {
+            // The direction bits are ordered clockwise, so rotating the bits rotates the particles
+            self.raw = if chirality {
+                ((self.raw << 1) | (self.raw >> 5)) & 0b00111111
+            } else {
+                ((self.raw >> 1) | (self.raw << 5)) & 0b00111111
+            };
+        }
+    }
+}
+
+/// Collision rules that can be selected at runtime.
+///
+/// The optimized kernels pick their rules at compile time with `use_real_collisions_in_core`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CollisionModel {
+    /// Randomized FHP collisions from `process_collision`
+    Real,
+    /// Deterministic collisions from `movement_core` that use the neighbors instead of a random number
+    Fake,
+    /// Deterministic collisions from `process_chiral_collision` that always rotate in the same direction
+    Chiral { clockwise: bool },
+}
+
+impl CollisionModel {
+    pub const ALL: [CollisionModel; 4] = [
+        CollisionModel::Real,
+        CollisionModel::Fake,
+        CollisionModel::Chiral { clockwise: true },
+        CollisionModel::Chiral { clockwise: false },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CollisionModel::Real => "real",
+            CollisionModel::Fake => "fake",
+            CollisionModel::Chiral { clockwise: true } => "clockwise",
+            CollisionModel::Chiral { clockwise: false } => "counterclockwise",
+        }
+    }
 }
 
 #[cfg(test)]
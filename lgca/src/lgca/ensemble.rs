@@ -0,0 +1,222 @@
+use mpi::{
+    collective::SystemOperation,
+    topology::{Color, SimpleCommunicator},
+    traits::*,
+};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use super::fields::CoarseFields;
+
+/// Mean and sample variance of the coarse fields over all members of an ensemble
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnsembleFields {
+    pub mean: CoarseFields,
+    pub variance: CoarseFields,
+}
+
+impl EnsembleFields {
+    /// Combine the fields of all members. All fields need the same size
+    pub fn from_members(members: &[CoarseFields]) -> Self {
+        let sums: Vec<Vec<f64>> = members.iter().map(sums_of).collect();
+        Self::from_sums(&members[0], &add(&sums), members.len())
+    }
+
+    /// `sums` holds the sums of all fields followed by the sums of their squares, as returned by `sums_of`
+    fn from_sums(shape: &CoarseFields, sums: &[f64], members: usize) -> Self {
+        let blocks = shape.density.len();
+        let count = members as f64;
+        let mean: Vec<f32> = sums[..3 * blocks]
+            .iter()
+            .map(|sum| (sum / count) as f32)
+            .collect();
+        let variance: Vec<f32> = sums[..3 * blocks]
+            .iter()
+            .zip(sums[3 * blocks..].iter())
+            .map(|(sum, squares)| {
+                if members < 2 {
+                    return 0.0;
+                }
+                ((squares - sum * sum / count) / (count - 1.0)).max(0.0) as f32
+            })
+            .collect();
+        let unpack = |values: &[f32]| CoarseFields {
+            density: values[..blocks].to_vec(),
+            velocity_x: values[blocks..2 * blocks].to_vec(),
+            velocity_y: values[2 * blocks..].to_vec(),
+            ..shape.clone()
+        };
+        Self {
+            mean: unpack(&mean),
+            variance: unpack(&variance),
+        }
+    }
+}
+
+/// All fields one after another, followed by their squares
+fn sums_of(fields: &CoarseFields) -> Vec<f64> {
+    let values: Vec<f64> = fields
+        .density
+        .iter()
+        .chain(fields.velocity_x.iter())
+        .chain(fields.velocity_y.iter())
+        .map(|value| *value as f64)
+        .collect();
+    let squares: Vec<f64> = values.iter().map(|value| value * value).collect();
+    [values, squares].concat()
+}
+
+fn add(sums: &[Vec<f64>]) -> Vec<f64> {
+    let mut total = vec![0.0; sums[0].len()];
+    for sum in sums {
+        for (total, value) in total.iter_mut().zip(sum) {
+            *total += value;
+        }
+    }
+    total
+}
+
+fn member_directory(output_directory: &Path, member: usize) -> PathBuf {
+    output_directory.join(format!("member_{}", member))
+}
+
+/// Independent realizations of the same simulation on disjoint groups of ranks.
+///
+/// The world is split into `members` groups of consecutive ranks. Every group simulates the whole grid,
+/// and ranks at the same position in their groups own the same strip of rows, even if the strips differ in length.
+pub struct Ensemble {
+    pub members: usize,
+    /// Index of the realization this rank belongs to
+    pub member: usize,
+    /// Ranks of the same realization
+    pub communicator: SimpleCommunicator,
+    /// Ranks of all realizations that own the same strip
+    across: SimpleCommunicator,
+}
+
+impl Ensemble {
+    pub fn split(world: &SimpleCommunicator, members: usize) -> Self {
+        let size = world.size() as usize;
+        if members == 0 || size % members != 0 {
+            panic!(
+                "{} ranks can not be split into {} ensemble members",
+                size, members
+            );
+        }
+        let ranks_per_member = size / members;
+        let rank = world.rank() as usize;
+        let member = rank / ranks_per_member;
+        let communicator = world
+            .split_by_color(Color::with_value(member as i32))
+            .unwrap();
+        let across = world
+            .split_by_color(Color::with_value((rank % ranks_per_member) as i32))
+            .unwrap();
+        Self {
+            members,
+            member,
+            communicator,
+            across,
+        }
+    }
+
+    /// Directory inside `output_directory` for the files of this member, so that members do not overwrite each other
+    pub fn member_directory(&self, output_directory: &Path) -> PathBuf {
+        member_directory(output_directory, self.member)
+    }
+
+    /// Average the fields of this strip over all members. Collective, every rank has to call it
+    pub fn average(&self, fields: &CoarseFields) -> EnsembleFields {
+        let sums = sums_of(fields);
+        let mut total = vec![0.0; sums.len()];
+        self.across
+            .all_reduce_into(&sums[..], &mut total[..], SystemOperation::sum());
+        EnsembleFields::from_sums(fields, &total, self.members)
+    }
+
+    /// Average the fields over all members and write them as CSV with one line per block.
+    ///
+    /// Only the first member writes, its first rank collects the strips of the others.
+    /// Collective, every rank has to call it.
+    pub fn write_average(&self, fields: &CoarseFields, path: &Path) {
+        let average = self.average(fields);
+        if self.member != 0 {
+            return;
+        }
+        let mut values: Vec<f32> = Vec::with_capacity(6 * fields.density.len());
+        for field in [&average.mean, &average.variance] {
+            values.extend(&field.density);
+            values.extend(&field.velocity_x);
+            values.extend(&field.velocity_y);
+        }
+
+        if self.communicator.rank() != 0 {
+            self.communicator.process_at_rank(0).send(&values[..]);
+            return;
+        }
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+        writeln!(file, "block_x,block_y,density,density_variance,velocity_x,velocity_x_variance,velocity_y,velocity_y_variance").unwrap();
+        // Strips can differ in length, so every strip is received on its own and placed below the previous one
+        let mut block_row_offset = 0;
+        for strip in 0..self.communicator.size() {
+            let strip_values = if strip == 0 {
+                values.clone()
+            } else {
+                self.communicator
+                    .process_at_rank(strip)
+                    .receive_vec::<f32>()
+                    .0
+            };
+            let blocks = strip_values.len() / 6;
+            for block in 0..blocks {
+                let field = |index: usize| strip_values[index * blocks + block];
+                writeln!(
+                    file,
+                    "{},{},{},{},{},{},{},{}",
+                    block % fields.width,
+                    block_row_offset + block / fields.width,
+                    field(0),
+                    field(3),
+                    field(1),
+                    field(4),
+                    field(2),
+                    field(5)
+                )
+                .unwrap();
+            }
+            block_row_offset += blocks / fields.width;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_and_sample_variance() {
+        let fields = |density: f32| CoarseFields {
+            width: 1,
+            height: 1,
+            block_size: 4,
+            density: vec![density],
+            velocity_x: vec![0.0],
+            velocity_y: vec![1.0],
+        };
+        let average = EnsembleFields::from_members(&[fields(0.1), fields(0.3), fields(0.5)]);
+        assert!((average.mean.density[0] - 0.3).abs() < 1e-6);
+        assert!((average.variance.density[0] - 0.04).abs() < 1e-6);
+        assert_eq!(average.mean.velocity_y[0], 1.0);
+        assert_eq!(average.variance.velocity_x[0], 0.0);
+    }
+
+    #[test]
+    fn members_write_into_their_own_directories() {
+        let output = Path::new("results");
+        assert_eq!(member_directory(output, 0), Path::new("results/member_0"));
+        assert_ne!(member_directory(output, 0), member_directory(output, 1));
+    }
+}
@@ -0,0 +1,38 @@
+use clap::Subcommand;
+
+pub mod anisotropy;
+pub mod equilibrium;
+pub mod permeability;
+pub mod phase_separation;
+pub mod viscosity;
+
+#[derive(Subcommand)]
+pub enum Experiment {
+    /// Measure the kinematic viscosity from the decay of a shear wave
+    Viscosity(viscosity::ViscosityArgs),
+    /// Let a mixture of two immiscible species separate
+    PhaseSeparation(phase_separation::PhaseSeparationArgs),
+    /// Compare the viscosity of shear waves in different orientations for FHP and HPP
+    Anisotropy(anisotropy::AnisotropyArgs),
+    /// Compare the equilibrium of all collision models with the Fermi-Dirac distribution
+    Equilibrium(equilibrium::EquilibriumArgs),
+    /// Measure the Darcy permeability of a random porous medium
+    Permeability(permeability::PermeabilityArgs),
+}
+
+impl Experiment {
+    /// Run the experiment and print its results as CSV to stdout
+    pub fn run<const WIDTH: usize>(&self)
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        match self {
+            Experiment::Viscosity(args) => args.run::<WIDTH>(),
+            Experiment::PhaseSeparation(args) => args.run::<WIDTH>(),
+            Experiment::Anisotropy(args) => args.run::<WIDTH>(),
+            Experiment::Equilibrium(args) => args.run::<WIDTH>(),
+            Experiment::Permeability(args) => args.run::<WIDTH>(),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use clap::Args;
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+use super::viscosity::fit_decay;
+use crate::lgca::{
+    lattice::{lattice_round, Fhp, Hpp, LatticeModel},
+    Cell,
+};
+
+/// A transverse shear wave that fits on the periodic grid.
+///
+/// The wave vector has `periods.0` periods along the width and `periods.1` periods along the height.
+pub struct ShearWave {
+    pub periods: (usize, usize),
+    pub amplitude: f64,
+}
+
+impl ShearWave {
+    fn wave_vector<M: LatticeModel>(&self, width: usize, height: usize) -> (f64, f64) {
+        (
+            2.0 * PI * self.periods.0 as f64 / width as f64,
+            2.0 * PI * self.periods.1 as f64 / (height as f64 * M::ROW_DISTANCE),
+        )
+    }
+
+    /// Direction of the velocity, perpendicular to the wave vector
+    fn polarization<M: LatticeModel>(&self, width: usize, height: usize) -> (f64, f64) {
+        let (k_x, k_y) = self.wave_vector::<M>(width, height);
+        let length = (k_x * k_x + k_y * k_y).sqrt();
+        (-k_y / length, k_x / length)
+    }
+
+    /// Angle of the wave vector in degrees
+    pub fn angle<M: LatticeModel>(&self, width: usize, height: usize) -> f64 {
+        let (k_x, k_y) = self.wave_vector::<M>(width, height);
+        k_y.atan2(k_x).to_degrees()
+    }
+
+    fn phase<M: LatticeModel>(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let (k_x, k_y) = self.wave_vector::<M>(width, height);
+        let (position_x, position_y) = M::position(x, y);
+        k_x * position_x + k_y * position_y
+    }
+}
+
+/// Fill the grid with the linearized equilibrium `d * (1 + 2 * c_i * u)` of the shear wave
+fn initialize<M: LatticeModel, const WIDTH: usize>(
+    grid: &mut [[Cell; WIDTH]],
+    wave: &ShearWave,
+    density: f64,
+    random: &mut impl Rng,
+) {
+    let height = grid.len();
+    let (e_x, e_y) = wave.polarization::<M>(WIDTH, height);
+    for (y, row) in grid.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let velocity = wave.amplitude * wave.phase::<M>(x, y, WIDTH, height).sin();
+            cell.raw = 0;
+            for direction in M::DIRECTIONS {
+                let (c_x, c_y) = direction.velocity;
+                let projected = c_x * e_x + c_y * e_y;
+                let probability = (density * (1.0 + 2.0 * projected * velocity)).clamp(0.0, 1.0);
+                if random.gen_bool(probability) {
+                    cell.raw |= direction.bit;
+                }
+            }
+        }
+    }
+}
+
+/// Project the momentum along the polarization on the initial sine mode
+fn amplitude<M: LatticeModel, const WIDTH: usize>(grid: &[[Cell; WIDTH]], wave: &ShearWave) -> f64 {
+    let height = grid.len();
+    let (e_x, e_y) = wave.polarization::<M>(WIDTH, height);
+    let mut projection = 0.0;
+    for (y, row) in grid.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let momentum: f64 = M::DIRECTIONS
+                .iter()
+                .filter(|direction| cell.raw & direction.bit != 0)
+                .map(|direction| direction.velocity.0 * e_x + direction.velocity.1 * e_y)
+                .sum();
+            projection += momentum * wave.phase::<M>(x, y, WIDTH, height).sin();
+        }
+    }
+    2.0 * projection / (height * WIDTH) as f64
+}
+
+pub struct AnisotropyResult {
+    pub periods: (usize, usize),
+    /// Angle of the wave vector in degrees
+    pub angle: f64,
+    pub fitted_rounds: usize,
+    /// Viscosity measured from the decay of this wave
+    pub viscosity: f64,
+}
+
+pub struct AnisotropyExperiment {
+    /// Number of rows. Must be a multiple of the row period of the model
+    pub height: usize,
+    /// Probability that a channel is occupied
+    pub density: f64,
+    pub amplitude: f64,
+    pub rounds: usize,
+    pub seed: u64,
+}
+
+impl AnisotropyExperiment {
+    /// Measure the viscosity of a model with shear waves in different orientations.
+    ///
+    /// For an isotropic model all orientations give the same value.
+    pub fn run<M: LatticeModel, const WIDTH: usize>(
+        &self,
+        periods: &[(usize, usize)],
+    ) -> Vec<AnisotropyResult> {
+        let mut grid_a = vec![[Cell::new(); WIDTH]; self.height];
+        let mut grid_b = vec![[Cell::new(); WIDTH]; self.height];
+
+        periods
+            .iter()
+            .map(|periods| {
+                let wave = ShearWave {
+                    periods: *periods,
+                    amplitude: self.amplitude,
+                };
+                let random = &mut SmallRng::seed_from_u64(self.seed);
+                initialize::<M, WIDTH>(&mut grid_a, &wave, self.density, random);
+
+                let mut amplitudes = Vec::with_capacity(self.rounds + 1);
+                amplitudes.push(amplitude::<M, WIDTH>(&grid_a, &wave));
+                for _ in 0..self.rounds {
+                    lattice_round::<M, WIDTH>(&grid_a, &mut grid_b);
+                    std::mem::swap(&mut grid_a, &mut grid_b);
+                    amplitudes.push(amplitude::<M, WIDTH>(&grid_a, &wave));
+                }
+
+                let (k_x, k_y) = wave.wave_vector::<M>(WIDTH, self.height);
+                let (viscosity, fitted_rounds) =
+                    fit_decay(&amplitudes, (k_x * k_x + k_y * k_y).sqrt());
+                AnisotropyResult {
+                    periods: *periods,
+                    angle: wave.angle::<M>(WIDTH, self.height),
+                    fitted_rounds,
+                    viscosity,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Args)]
+pub struct AnisotropyArgs {
+    /// Number of rows. The width is the compiled in width
+    #[arg(long, default_value_t = 100)]
+    pub height: usize,
+
+    /// Probability that a channel is occupied
+    #[arg(long, default_value_t = 0.2)]
+    pub density: f64,
+
+    /// Initial amplitude of the shear velocity
+    #[arg(long, default_value_t = 0.1)]
+    pub amplitude: f64,
+
+    /// Rounds per orientation
+    #[arg(long, default_value_t = 500)]
+    pub rounds: usize,
+
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+/// Print a CSV line for every orientation of the shear wave
+fn print_anisotropy<M: LatticeModel, const WIDTH: usize>(experiment: &AnisotropyExperiment) {
+    eprintln!("Measuring anisotropy of {}", M::NAME);
+    for result in experiment.run::<M, WIDTH>(&[(0, 1), (1, 0), (1, 1)]) {
+        println!(
+            "{},{},{},{},{},{}",
+            M::NAME,
+            result.periods.0,
+            result.periods.1,
+            result.angle,
+            result.fitted_rounds,
+            result.viscosity
+        );
+    }
+}
+
+impl AnisotropyArgs {
+    /// Run the experiment and print its results as CSV to stdout
+    pub fn run<const WIDTH: usize>(&self) {
+        if self.height % 2 != 0 {
+            panic!("The height of the anisotropy experiment must be even");
+        }
+        let experiment = AnisotropyExperiment {
+            height: self.height,
+            density: self.density,
+            amplitude: self.amplitude,
+            rounds: self.rounds,
+            seed: self.seed,
+        };
+
+        println!("model,periods_x,periods_y,angle,fitted_rounds,viscosity");
+        print_anisotropy::<Fhp, WIDTH>(&experiment);
+        print_anisotropy::<Hpp, WIDTH>(&experiment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polarization_is_perpendicular() {
+        let wave = ShearWave {
+            periods: (1, 2),
+            amplitude: 0.1,
+        };
+        let (k_x, k_y) = wave.wave_vector::<Fhp>(40, 30);
+        let (e_x, e_y) = wave.polarization::<Fhp>(40, 30);
+        assert!((k_x * e_x + k_y * e_y).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hpp_shear_wave_along_axis_does_not_decay() {
+        let experiment = AnisotropyExperiment {
+            height: 32,
+            density: 0.3,
+            amplitude: 0.1,
+            rounds: 60,
+            seed: 0,
+        };
+        let hpp = experiment.run::<Hpp, 32>(&[(0, 1), (1, 1)]);
+        assert!(hpp[0].viscosity.abs() < 0.01, "{}", hpp[0].viscosity);
+        assert!(hpp[1].viscosity > 0.05, "{}", hpp[1].viscosity);
+    }
+}
@@ -0,0 +1,337 @@
+use clap::Args;
+use rand::prelude::*;
+
+use crate::lgca::{
+    cell::CollisionModel,
+    lattice::{Fhp, LatticeModel},
+    periodic::periodic_round,
+    Cell,
+};
+
+/// Names of the directions in the order of `Fhp::DIRECTIONS`
+pub const DIRECTION_NAMES: [&str; 6] = [
+    "east",
+    "north_east",
+    "north_west",
+    "west",
+    "south_west",
+    "south_east",
+];
+
+pub struct EquilibriumExperiment {
+    /// Number of rows. Must be even
+    pub height: usize,
+    /// Probability that a channel is occupied
+    pub density: f64,
+    /// Mean velocity of the initial state
+    pub velocity: (f64, f64),
+    /// Rounds before the measurement starts
+    pub relaxation: usize,
+    /// Rounds that get averaged
+    pub samples: usize,
+    pub seed: u64,
+}
+
+pub struct EquilibriumResult {
+    pub collision_model: CollisionModel,
+    /// Mean occupation of every direction, in the order of `Fhp::DIRECTIONS`
+    pub occupations: [f64; 6],
+    /// Fermi-Dirac distribution with the same mass and momentum
+    pub fermi_dirac: [f64; 6],
+}
+
+/// Fermi-Dirac distribution `f_i = 1 / (1 + exp(h + q * c_i))` with the given mass and momentum per cell.
+///
+/// Solves for `h` and `q` with Newton's method.
+pub fn fermi_dirac(mass: f64, momentum: (f64, f64)) -> [f64; 6] {
+    assert!(
+        mass > 0.0 && mass < 6.0,
+        "A Fermi-Dirac distribution needs a mass between 0 and 6, not {}",
+        mass
+    );
+    let distribution = |[h, q_x, q_y]: [f64; 3]| -> [f64; 6] {
+        std::array::from_fn(|i| {
+            let (c_x, c_y) = Fhp::DIRECTIONS[i].velocity;
+            1.0 / (1.0 + (h + q_x * c_x + q_y * c_y).exp())
+        })
+    };
+
+    let mut parameters = [(6.0 / mass - 1.0).ln(), 0.0, 0.0];
+    for _ in 0..100 {
+        let f = distribution(parameters);
+        // Residuals of mass and momentum, and their derivatives by h, q_x and q_y
+        let mut residual = [-mass, -momentum.0, -momentum.1];
+        let mut jacobian = [[0.0; 3]; 3];
+        for (i, f) in f.iter().enumerate() {
+            let (c_x, c_y) = Fhp::DIRECTIONS[i].velocity;
+            let moments = [1.0, c_x, c_y];
+            for row in 0..3 {
+                residual[row] += f * moments[row];
+                for column in 0..3 {
+                    jacobian[row][column] -= f * (1.0 - f) * moments[row] * moments[column];
+                }
+            }
+        }
+        let step = solve(jacobian, residual);
+        for (parameter, step) in parameters.iter_mut().zip(step) {
+            *parameter -= step;
+        }
+        if step.iter().all(|step| step.abs() < 1e-14) {
+            break;
+        }
+    }
+    distribution(parameters)
+}
+
+/// Solve a 3x3 system with Cramer's rule
+fn solve(matrix: [[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    let determinant = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let full = determinant(matrix);
+    std::array::from_fn(|column| {
+        let mut replaced = matrix;
+        for row in 0..3 {
+            replaced[row][column] = vector[row];
+        }
+        determinant(replaced) / full
+    })
+}
+
+/// Momentum flux tensor `sum(f_i * c_i * c_i)` of a distribution
+pub fn momentum_flux(distribution: &[f64; 6]) -> [[f64; 2]; 2] {
+    let mut flux = [[0.0; 2]; 2];
+    for (f, direction) in distribution.iter().zip(Fhp::DIRECTIONS) {
+        let c = [direction.velocity.0, direction.velocity.1];
+        for a in 0..2 {
+            for b in 0..2 {
+                flux[a][b] += f * c[a] * c[b];
+            }
+        }
+    }
+    flux
+}
+
+impl EquilibriumResult {
+    pub fn mass(&self) -> f64 {
+        self.occupations.iter().sum()
+    }
+
+    pub fn momentum(&self) -> (f64, f64) {
+        self.occupations
+            .iter()
+            .zip(Fhp::DIRECTIONS)
+            .fold((0.0, 0.0), |(x, y), (f, direction)| {
+                (x + f * direction.velocity.0, y + f * direction.velocity.1)
+            })
+    }
+
+    /// Largest difference between a measured occupation and the Fermi-Dirac prediction
+    pub fn occupation_error(&self) -> f64 {
+        self.occupations
+            .iter()
+            .zip(self.fermi_dirac)
+            .map(|(measured, predicted)| (measured - predicted).abs())
+            .fold(0.0, f64::max)
+    }
+
+    /// Largest difference between the measured momentum flux tensor and the one of the Fermi-Dirac distribution.
+    ///
+    /// Without flow the prediction is isotropic: `Pi_xx = Pi_yy` and `Pi_xy = 0`.
+    pub fn anisotropy(&self) -> f64 {
+        let measured = momentum_flux(&self.occupations);
+        let predicted = momentum_flux(&self.fermi_dirac);
+        measured
+            .iter()
+            .flatten()
+            .zip(predicted.iter().flatten())
+            .map(|(measured, predicted)| (measured - predicted).abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+impl EquilibriumExperiment {
+    /// Let a uniform state relax and compare the occupation of every direction with the Fermi-Dirac equilibrium.
+    ///
+    /// A collision model that prefers some directions over others shows up as a difference.
+    pub fn run<const WIDTH: usize>(&self, collision_model: CollisionModel) -> EquilibriumResult {
+        let mut grid_a = vec![[Cell::new(); WIDTH]; self.height];
+        let mut grid_b = vec![[Cell::new(); WIDTH]; self.height];
+
+        let random = &mut SmallRng::seed_from_u64(self.seed);
+        for cell in grid_a.iter_mut().flatten() {
+            for direction in Fhp::DIRECTIONS {
+                let (c_x, c_y) = direction.velocity;
+                let projected = c_x * self.velocity.0 + c_y * self.velocity.1;
+                let probability = (self.density * (1.0 + 2.0 * projected)).clamp(0.0, 1.0);
+                if random.gen_bool(probability) {
+                    cell.raw |= direction.bit;
+                }
+            }
+        }
+
+        let mut counts = [0u64; 6];
+        for round in 0..self.relaxation + self.samples {
+            periodic_round(&grid_a, &mut grid_b, collision_model);
+            std::mem::swap(&mut grid_a, &mut grid_b);
+            if round < self.relaxation {
+                continue;
+            }
+            for cell in grid_a.iter().flatten() {
+                for (count, direction) in counts.iter_mut().zip(Fhp::DIRECTIONS) {
+                    *count += (cell.raw & direction.bit != 0) as u64;
+                }
+            }
+        }
+
+        let cells = (WIDTH * self.height * self.samples) as f64;
+        let mut result = EquilibriumResult {
+            collision_model,
+            occupations: counts.map(|count| count as f64 / cells),
+            fermi_dirac: [0.0; 6],
+        };
+        // Mass and momentum are conserved, so the prediction can use the measured ones
+        result.fermi_dirac = fermi_dirac(result.mass(), result.momentum());
+        result
+    }
+}
+
+#[derive(Args)]
+pub struct EquilibriumArgs {
+    /// Number of rows. The width is the compiled in width
+    #[arg(long, default_value_t = 64)]
+    pub height: usize,
+
+    /// Probability that a channel is occupied
+    #[arg(long, default_value_t = 0.3)]
+    pub density: f64,
+
+    /// Mean velocity of the initial state in x direction
+    #[arg(long, default_value_t = 0.0)]
+    pub velocity_x: f64,
+
+    /// Mean velocity of the initial state in y direction, pointing south
+    #[arg(long, default_value_t = 0.0)]
+    pub velocity_y: f64,
+
+    /// Rounds before the measurement starts
+    #[arg(long, default_value_t = 100)]
+    pub relaxation: usize,
+
+    /// Rounds that get averaged
+    #[arg(long, default_value_t = 1000)]
+    pub samples: usize,
+
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+impl EquilibriumArgs {
+    /// Run the experiment and print its results as CSV to stdout
+    pub fn run<const WIDTH: usize>(&self) {
+        if self.height % 2 != 0 {
+            panic!("The height of the equilibrium experiment must be even");
+        }
+        let experiment = EquilibriumExperiment {
+            height: self.height,
+            density: self.density,
+            velocity: (self.velocity_x, self.velocity_y),
+            relaxation: self.relaxation,
+            samples: self.samples,
+            seed: self.seed,
+        };
+
+        println!("collision_model,quantity,measured,fermi_dirac");
+        for collision_model in CollisionModel::ALL {
+            eprintln!(
+                "Measuring equilibrium of {} collisions",
+                collision_model.name()
+            );
+            let result = experiment.run::<WIDTH>(collision_model);
+            for (name, (measured, predicted)) in DIRECTION_NAMES
+                .iter()
+                .zip(result.occupations.iter().zip(result.fermi_dirac))
+            {
+                println!(
+                    "{},{},{},{}",
+                    collision_model.name(),
+                    name,
+                    measured,
+                    predicted
+                );
+            }
+            let measured = momentum_flux(&result.occupations);
+            let predicted = momentum_flux(&result.fermi_dirac);
+            for (name, (a, b)) in [("pi_xx", (0, 0)), ("pi_xy", (0, 1)), ("pi_yy", (1, 1))] {
+                println!(
+                    "{},{},{},{}",
+                    collision_model.name(),
+                    name,
+                    measured[a][b],
+                    predicted[a][b]
+                );
+            }
+            eprintln!(
+                "Largest occupation error {}, largest momentum flux error {}",
+                result.occupation_error(),
+                result.anisotropy()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fermi_dirac_has_the_given_mass_and_momentum() {
+        let fermi_dirac = fermi_dirac(1.8, (0.3, -0.1));
+        let result = EquilibriumResult {
+            collision_model: CollisionModel::Real,
+            occupations: fermi_dirac,
+            fermi_dirac,
+        };
+        assert!((result.mass() - 1.8).abs() < 1e-12);
+        assert!((result.momentum().0 - 0.3).abs() < 1e-12);
+        assert!((result.momentum().1 + 0.1).abs() < 1e-12);
+
+        let at_rest = momentum_flux(&super::fermi_dirac(1.8, (0.0, 0.0)));
+        assert!((at_rest[0][0] - at_rest[1][1]).abs() < 1e-12);
+        assert!(at_rest[0][1].abs() < 1e-12);
+    }
+
+    #[test]
+    fn no_collision_model_prefers_a_direction() {
+        for velocity in [(0.0, 0.0), (0.15, 0.0), (0.05, -0.1)] {
+            let experiment = EquilibriumExperiment {
+                height: 64,
+                density: 0.3,
+                velocity,
+                relaxation: 50,
+                samples: 400,
+                seed: 3,
+            };
+            for collision_model in CollisionModel::ALL {
+                let result = experiment.run::<64>(collision_model);
+                assert!(
+                    result.occupation_error() < 0.01,
+                    "{} collisions with velocity {:?}: {:?} != {:?}",
+                    collision_model.name(),
+                    velocity,
+                    result.occupations,
+                    result.fermi_dirac
+                );
+                assert!(
+                    result.anisotropy() < 0.01,
+                    "{} collisions with velocity {:?} are anisotropic: {:?}",
+                    collision_model.name(),
+                    velocity,
+                    momentum_flux(&result.occupations)
+                );
+            }
+        }
+    }
+}
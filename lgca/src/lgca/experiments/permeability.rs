@@ -0,0 +1,240 @@
+use clap::Args;
+
+use super::viscosity::ViscosityExperiment;
+use crate::lgca::{
+    boundary::{Edge, EdgeBoundary},
+    cell::CollisionModel,
+    forcing::BodyForce,
+    porous::PorousMedium,
+    simulation::Simulation,
+};
+
+pub struct PermeabilityExperiment {
+    /// Number of rows. Must be even
+    pub height: usize,
+    pub medium: PorousMedium,
+    /// Probability that a channel in the pores is occupied
+    pub density: f64,
+    /// Probability per cell and round that a particle gets flipped to the east
+    pub body_force: f64,
+    /// Rounds until the flow is steady
+    pub relaxation: usize,
+    /// Rounds that get averaged
+    pub samples: usize,
+}
+
+pub struct PermeabilityResult {
+    /// Fraction of fluid cells
+    pub porosity: f64,
+    /// Mean x momentum per cell, solid cells included. This is the Darcy flux times the density
+    pub flux: f64,
+    /// Mean force per cell on the grains. In the steady state it balances the body force
+    pub force: f64,
+}
+
+impl PermeabilityResult {
+    /// Darcy permeability `k = nu * porosity * flux / force` for the given kinematic viscosity of the fluid.
+    ///
+    /// The body force acts on the fluid only, so the pressure gradient it stands for is the force per fluid cell.
+    pub fn permeability(&self, viscosity: f64) -> f64 {
+        viscosity * self.porosity * self.flux / self.force
+    }
+}
+
+impl PermeabilityExperiment {
+    /// Push a fluid through a random porous medium with periodic edges and measure the flux in the steady state.
+    ///
+    /// The force comes from the momentum exchange with the grains, so the flips of the body force that were not
+    /// possible do not matter.
+    pub fn run<const WIDTH: usize>(&self) -> PermeabilityResult
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        let mut simulation = Simulation::<WIDTH>::new(self.height, None);
+        simulation.set_boundary(Box::new(EdgeBoundary::new(
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+        )));
+        simulation.add_seeded_noise(self.density, self.medium.seed);
+        simulation.set_obstacles(self.medium.generate(WIDTH, self.height));
+        simulation.set_body_force(BodyForce::new(self.body_force));
+
+        let cells = (WIDTH * self.height) as f64;
+        let (mut flux, mut force) = (0.0, 0.0);
+        for round in 0..self.relaxation + self.samples {
+            simulation.step();
+            if round < self.relaxation {
+                continue;
+            }
+            flux += simulation
+                .grid()
+                .iter()
+                .flatten()
+                .map(|cell| cell.get_momentum().0 as f64)
+                .sum::<f64>()
+                / cells;
+            force += simulation
+                .obstacles()
+                .momentum_transfer
+                .iter()
+                .map(|(x, _)| x)
+                .sum::<f64>()
+                / cells;
+        }
+
+        PermeabilityResult {
+            porosity: 1.0 - simulation.obstacles().solid_cells() as f64 / cells,
+            flux: flux / self.samples as f64,
+            force: force / self.samples as f64,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PermeabilityArgs {
+    /// Number of rows. The width is the compiled in width
+    #[arg(long, default_value_t = 100)]
+    pub height: usize,
+
+    /// Fraction of the cells that stay fluid
+    #[arg(long, default_value_t = 0.7)]
+    pub porosity: f64,
+
+    /// Radius of the grains in cell widths
+    #[arg(long, default_value_t = 4.0)]
+    pub grain_radius: f64,
+
+    /// Probability that a channel in the pores is occupied
+    #[arg(long, default_value_t = 0.2)]
+    pub density: f64,
+
+    /// Probability per cell and round that a particle gets flipped to the east
+    #[arg(long, default_value_t = 0.02)]
+    pub body_force: f64,
+
+    /// Rounds until the flow is steady
+    #[arg(long, default_value_t = 1000)]
+    pub relaxation: usize,
+
+    /// Rounds that get averaged
+    #[arg(long, default_value_t = 1000)]
+    pub samples: usize,
+
+    /// Seed of the grains, the initial particles and the shear waves
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Kinematic viscosity of the fluid. Measured with shear waves at the density in the pores if not given
+    #[arg(long)]
+    pub viscosity: Option<f64>,
+
+    /// Number of rows of the shear waves that measure the viscosity
+    #[arg(long, default_value_t = 64)]
+    pub viscosity_height: usize,
+
+    /// Initial amplitude of the shear waves that measure the viscosity
+    #[arg(long, default_value_t = 0.1)]
+    pub viscosity_amplitude: f64,
+
+    /// Maximum number of rounds per shear wave
+    #[arg(long, default_value_t = 500)]
+    pub viscosity_rounds: usize,
+
+    /// Number of shear waves that get averaged
+    #[arg(long, default_value_t = 4)]
+    pub viscosity_realizations: usize,
+}
+
+impl PermeabilityArgs {
+    /// Run the experiment and print its results as CSV to stdout
+    pub fn run<const WIDTH: usize>(&self)
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        if self.height % 2 != 0 {
+            panic!("The height of the permeability experiment must be even");
+        }
+        if self.viscosity.is_none() && self.viscosity_height % 2 != 0 {
+            panic!("The height of the shear waves that measure the viscosity must be even");
+        }
+        let experiment = PermeabilityExperiment {
+            height: self.height,
+            medium: PorousMedium {
+                porosity: self.porosity,
+                radius: self.grain_radius,
+                seed: self.seed,
+            },
+            density: self.density,
+            body_force: self.body_force,
+            relaxation: self.relaxation,
+            samples: self.samples,
+        };
+        eprintln!("Pushing the fluid through the porous medium");
+        let result = experiment.run::<WIDTH>();
+
+        let viscosity = self.viscosity.unwrap_or_else(|| {
+            // The kernels of the simulation pick their collision rules at compile time
+            let collision_model = if cfg!(use_real_collisions_in_core) {
+                CollisionModel::Real
+            } else {
+                CollisionModel::Fake
+            };
+            eprintln!(
+                "Measuring viscosity of {} collisions at the density in the pores",
+                collision_model.name()
+            );
+            ViscosityExperiment {
+                height: self.viscosity_height,
+                density: self.density,
+                amplitude: self.viscosity_amplitude,
+                rounds: self.viscosity_rounds,
+                realizations: self.viscosity_realizations,
+                seed: self.seed,
+            }
+            .run::<WIDTH>(collision_model)
+            .viscosity
+        });
+
+        println!("porosity,flux,force,viscosity,permeability");
+        println!(
+            "{},{},{},{},{}",
+            result.porosity,
+            result.flux,
+            result.force,
+            viscosity,
+            result.permeability(viscosity)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denser_media_are_less_permeable() {
+        let permeability = |porosity| {
+            let experiment = PermeabilityExperiment {
+                height: 64,
+                medium: PorousMedium {
+                    porosity,
+                    radius: 3.0,
+                    seed: 2,
+                },
+                density: 0.2,
+                body_force: 0.02,
+                relaxation: 300,
+                samples: 300,
+            };
+            let result = experiment.run::<64>();
+            assert!(result.porosity <= porosity && result.porosity > porosity - 0.05);
+            assert!(result.flux > 0.0 && result.force > 0.0);
+            result.permeability(1.0)
+        };
+        assert!(permeability(0.9) > 2.0 * permeability(0.7));
+    }
+}
@@ -0,0 +1,127 @@
+use clap::Args;
+use ril::{Image, Rgb};
+use std::{path::PathBuf, time::Duration};
+
+use crate::lgca::{
+    boundary::{Edge, EdgeBoundary},
+    simulation::Simulation,
+    species::segregation,
+    visualization::{draw_species, save_webp},
+    Cell,
+};
+
+pub struct PhaseSeparationExperiment {
+    /// Number of rows. Must be even
+    pub height: usize,
+    /// Probability that a channel is occupied
+    pub density: f64,
+    /// Probability that a particle is red
+    pub red_fraction: f64,
+    pub rounds: usize,
+    pub seed: u64,
+}
+
+impl PhaseSeparationExperiment {
+    /// Let a random mixture of two immiscible species separate on a grid that wraps around on all sides.
+    ///
+    /// `on_round` is called with the state before the first and after every round.
+    /// Returns the segregation for every round.
+    pub fn run<const WIDTH: usize>(
+        &self,
+        mut on_round: impl FnMut(usize, &[[Cell; WIDTH]], &[[Cell; WIDTH]]),
+    ) -> Vec<f64>
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        let mut simulation = Simulation::<WIDTH>::new(self.height, None);
+        simulation.set_boundary(Box::new(EdgeBoundary::new(
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+        )));
+        simulation.add_seeded_noise(self.density, self.seed);
+        simulation.set_species(self.red_fraction, self.seed);
+
+        let mut segregations = Vec::with_capacity(self.rounds + 1);
+        for round in 0..=self.rounds {
+            if round > 0 {
+                simulation.step();
+            }
+            let colors = simulation.colors().unwrap();
+            segregations.push(segregation(simulation.grid(), colors));
+            on_round(round, simulation.grid(), colors);
+        }
+        segregations
+    }
+}
+
+#[derive(Args)]
+pub struct PhaseSeparationArgs {
+    /// Number of rows. The width is the compiled in width
+    #[arg(long, default_value_t = 100)]
+    pub height: usize,
+
+    /// Probability that a channel is occupied
+    #[arg(long, default_value_t = 0.3)]
+    pub density: f64,
+
+    /// Probability that a particle belongs to the red species
+    #[arg(long, default_value_t = 0.5)]
+    pub red_fraction: f64,
+
+    #[arg(long, default_value_t = 1000)]
+    pub rounds: usize,
+
+    /// Render every nth round. 0 disables the output
+    #[arg(long, default_value_t = 10)]
+    pub frame_interval: usize,
+
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Where to write the animation
+    #[arg(long, default_value = "./phase_separation.webp")]
+    pub output: PathBuf,
+}
+
+impl PhaseSeparationArgs {
+    /// Run the experiment and print its results as CSV to stdout
+    pub fn run<const WIDTH: usize>(&self)
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        if self.height % 2 != 0 {
+            panic!("The height of the phase separation experiment must be even");
+        }
+        let experiment = PhaseSeparationExperiment {
+            height: self.height,
+            density: self.density,
+            red_fraction: self.red_fraction,
+            rounds: self.rounds,
+            seed: self.seed,
+        };
+
+        let mut images: Vec<Image<Rgb>> = Vec::new();
+        let segregations = experiment.run::<WIDTH>(|round, cells, colors| {
+            if self.frame_interval != 0 && round % self.frame_interval == 0 {
+                images.push(draw_species(cells, colors));
+            }
+        });
+
+        println!("round,segregation");
+        for (round, segregation) in segregations.iter().enumerate() {
+            println!("{},{}", round, segregation);
+        }
+
+        if self.frame_interval != 0 {
+            save_webp(
+                images,
+                Duration::from_secs_f64(1.0 / 30.0),
+                self.output.to_str().unwrap(),
+            );
+        }
+    }
+}
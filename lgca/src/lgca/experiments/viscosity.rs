@@ -0,0 +1,272 @@
+use clap::Args;
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+use crate::lgca::{
+    cell::{
+        CollisionModel, TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST,
+        TO_WEST,
+    },
+    periodic::periodic_round,
+    Cell,
+};
+
+/// Vertical distance between two rows of the hexagonal lattice
+const ROW_DISTANCE: f64 = 0.866_025_403_784_438_6;
+
+/// Directions with the x component of their unit vector
+const X_VELOCITIES: [(u8, f64); 6] = [
+    (TO_EAST, 1.0),
+    (TO_NORTH_EAST, 0.5),
+    (TO_SOUTH_EAST, 0.5),
+    (TO_WEST, -1.0),
+    (TO_NORTH_WEST, -0.5),
+    (TO_SOUTH_WEST, -0.5),
+];
+
+pub struct ViscosityExperiment {
+    /// Number of rows. Must be even
+    pub height: usize,
+    /// Probability that a channel is occupied
+    pub density: f64,
+    /// Initial amplitude of the shear velocity
+    pub amplitude: f64,
+    /// Maximum number of rounds to simulate
+    pub rounds: usize,
+    /// Number of independent runs that get averaged
+    pub realizations: usize,
+    pub seed: u64,
+}
+
+pub struct ViscosityResult {
+    pub collision_model: CollisionModel,
+    /// Averaged amplitude of the shear wave for every round
+    pub amplitudes: Vec<f64>,
+    /// Number of rounds that were used for the fit
+    pub fitted_rounds: usize,
+    pub viscosity: f64,
+}
+
+/// Fill the grid with a shear wave `u_x(y) = amplitude * sin(k * y)`
+fn initialize_shear_wave<const WIDTH: usize>(
+    grid: &mut [[Cell; WIDTH]],
+    density: f64,
+    amplitude: f64,
+    random: &mut impl Rng,
+) {
+    let height = grid.len();
+    for (y, row) in grid.iter_mut().enumerate() {
+        let velocity = amplitude * (2.0 * PI * y as f64 / height as f64).sin();
+        for cell in row.iter_mut() {
+            cell.raw = 0;
+            for (direction, x_velocity) in X_VELOCITIES {
+                // Linearized equilibrium for FHP: d * (1 + 2 * c_i * u)
+                let probability = (density * (1.0 + 2.0 * x_velocity * velocity)).clamp(0.0, 1.0);
+                if random.gen_bool(probability) {
+                    cell.raw |= direction;
+                }
+            }
+        }
+    }
+}
+
+/// Project the x momentum of all rows on the initial sine mode
+fn shear_wave_amplitude<const WIDTH: usize>(grid: &[[Cell; WIDTH]]) -> f64 {
+    let height = grid.len();
+    let projection: f64 = grid
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            let momentum: f64 = row
+                .iter()
+                .flat_map(|cell| {
+                    X_VELOCITIES
+                        .iter()
+                        .filter(|(direction, _)| cell.raw & direction != 0)
+                        .map(|(_, x_velocity)| x_velocity)
+                })
+                .sum();
+            momentum * (2.0 * PI * y as f64 / height as f64).sin()
+        })
+        .sum();
+    2.0 * projection / (height * WIDTH) as f64
+}
+
+/// Fit `ln(a(t)) = ln(a(0)) - nu * k^2 * t` with least squares.
+///
+/// Only uses the rounds before the amplitude first drops below `a(0) / e^2`,
+/// after that the noise dominates.
+pub fn fit_decay(amplitudes: &[f64], wave_number: f64) -> (f64, usize) {
+    let cutoff = amplitudes[0] * (-2.0f64).exp();
+    let samples: Vec<(f64, f64)> = amplitudes
+        .iter()
+        .take_while(|amplitude| **amplitude > cutoff)
+        .enumerate()
+        .map(|(round, amplitude)| (round as f64, amplitude.ln()))
+        .collect();
+    let count = samples.len() as f64;
+    if samples.len() < 2 {
+        return (f64::NAN, samples.len());
+    }
+
+    let mean_t = samples.iter().map(|(t, _)| t).sum::<f64>() / count;
+    let mean_a = samples.iter().map(|(_, a)| a).sum::<f64>() / count;
+    let covariance: f64 = samples
+        .iter()
+        .map(|(t, a)| (t - mean_t) * (a - mean_a))
+        .sum();
+    let variance: f64 = samples.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+    let slope = covariance / variance;
+
+    (-slope / (wave_number * wave_number), samples.len())
+}
+
+impl ViscosityExperiment {
+    /// Measure the kinematic viscosity of a collision model from the decay of a shear wave
+    pub fn run<const WIDTH: usize>(&self, collision_model: CollisionModel) -> ViscosityResult {
+        let mut amplitudes = vec![0.0; self.rounds + 1];
+        let mut grid_a = vec![[Cell::new(); WIDTH]; self.height];
+        let mut grid_b = vec![[Cell::new(); WIDTH]; self.height];
+
+        for realization in 0..self.realizations {
+            let random = &mut SmallRng::seed_from_u64(self.seed + realization as u64);
+            initialize_shear_wave(&mut grid_a, self.density, self.amplitude, random);
+            amplitudes[0] += shear_wave_amplitude(&grid_a);
+            for round in 1..=self.rounds {
+                periodic_round(&grid_a, &mut grid_b, collision_model);
+                std::mem::swap(&mut grid_a, &mut grid_b);
+                amplitudes[round] += shear_wave_amplitude(&grid_a);
+            }
+        }
+        for amplitude in amplitudes.iter_mut() {
+            *amplitude /= self.realizations as f64;
+        }
+
+        let wave_number = 2.0 * PI / (self.height as f64 * ROW_DISTANCE);
+        let (viscosity, fitted_rounds) = fit_decay(&amplitudes, wave_number);
+
+        ViscosityResult {
+            collision_model,
+            amplitudes,
+            fitted_rounds,
+            viscosity,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ViscosityArgs {
+    /// Number of rows. The width is the compiled in width
+    #[arg(long, default_value_t = 64)]
+    pub height: usize,
+
+    /// Probability that a channel is occupied
+    #[arg(long, default_value_t = 0.2)]
+    pub density: f64,
+
+    /// Initial amplitude of the shear velocity
+    #[arg(long, default_value_t = 0.1)]
+    pub amplitude: f64,
+
+    /// Maximum number of rounds per realization
+    #[arg(long, default_value_t = 500)]
+    pub rounds: usize,
+
+    /// Number of realizations that get averaged
+    #[arg(long, default_value_t = 4)]
+    pub realizations: usize,
+
+    /// Seed of the first realization
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+impl ViscosityArgs {
+    /// Run the experiment and print its results as CSV to stdout
+    pub fn run<const WIDTH: usize>(&self) {
+        if self.height % 2 != 0 {
+            panic!("The height of the viscosity experiment must be even");
+        }
+        let experiment = ViscosityExperiment {
+            height: self.height,
+            density: self.density,
+            amplitude: self.amplitude,
+            rounds: self.rounds,
+            realizations: self.realizations,
+            seed: self.seed,
+        };
+
+        println!("collision_model,width,height,density,amplitude,fitted_rounds,viscosity");
+        for collision_model in CollisionModel::ALL {
+            eprintln!(
+                "Measuring viscosity of {} collisions",
+                collision_model.name()
+            );
+            let result = experiment.run::<WIDTH>(collision_model);
+            println!(
+                "{},{},{},{},{},{},{}",
+                result.collision_model.name(),
+                WIDTH,
+                self.height,
+                self.density,
+                self.amplitude,
+                result.fitted_rounds,
+                result.viscosity
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_recovers_exponential_decay() {
+        let wave_number = 0.1;
+        let amplitudes: Vec<f64> = (0..200)
+            .map(|t| 0.3 * (-0.7 * wave_number * wave_number * t as f64).exp())
+            .collect();
+        let (viscosity, _) = fit_decay(&amplitudes, wave_number);
+        assert!((viscosity - 0.7).abs() < 1e-9, "{} != 0.7", viscosity);
+    }
+
+    #[test]
+    fn initial_amplitude_matches_shear_wave() {
+        const WIDTH: usize = 200;
+        let mut grid = vec![[Cell::new(); WIDTH]; 64];
+        let random = &mut SmallRng::seed_from_u64(0);
+        initialize_shear_wave(&mut grid, 0.3, 0.1, random);
+
+        // Each cell carries sum(2 * d * u * c_x^2) = 6 * d * u momentum in the mean
+        let expected = 6.0 * 0.3 * 0.1;
+        let amplitude = shear_wave_amplitude(&grid);
+        assert!(
+            (amplitude - expected).abs() < 0.03,
+            "{} != {}",
+            amplitude,
+            expected
+        );
+    }
+
+    #[test]
+    fn shear_waves_decay_for_all_collision_models() {
+        let experiment = ViscosityExperiment {
+            height: 32,
+            density: 0.3,
+            amplitude: 0.1,
+            rounds: 100,
+            realizations: 1,
+            seed: 1,
+        };
+        for collision_model in CollisionModel::ALL {
+            let result = experiment.run::<64>(collision_model);
+            assert!(
+                result.viscosity > 0.0,
+                "{} has viscosity {}",
+                collision_model.name(),
+                result.viscosity
+            );
+        }
+    }
+}
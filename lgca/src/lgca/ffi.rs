@@ -0,0 +1,257 @@
+//! C interface for embedding the simulation into other programs.
+//!
+//! The header `include/lgca.h` is generated from this file with `./generate_header.sh`.
+//! Functions that can fail return a negative value instead of panicking.
+//! A panic must not unwind into the host, so the simulation code runs under `catch_panic`.
+use std::{
+    ffi::{c_char, c_int, CStr},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use super::{scenario::Scenario, simulation::Simulation};
+use crate::WIDTH;
+
+/// Opaque handle to a simulation without MPI
+pub struct LgcaSimulation(Simulation<WIDTH>);
+
+/// A null pointer or an invalid argument was passed
+pub const LGCA_ERROR_ARGUMENT: c_int = -1;
+/// The scenario string could not be parsed
+pub const LGCA_ERROR_SCENARIO: c_int = -2;
+/// The output buffers are too small
+pub const LGCA_ERROR_BUFFER_SIZE: c_int = -3;
+/// The simulation panicked. The state of the simulation is unknown, it should only be destroyed
+pub const LGCA_ERROR_PANIC: c_int = -4;
+
+/// Run `body` and return `LGCA_ERROR_PANIC` if it panics
+fn catch_panic(body: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or(LGCA_ERROR_PANIC)
+}
+
+/// Number of cells in a row, fixed when the library was compiled
+#[no_mangle]
+pub extern "C" fn lgca_width() -> usize {
+    WIDTH
+}
+
+/// Create an empty simulation with `height` rows.
+///
+/// The height must be at least 2, otherwise null is returned. Null is also returned if the size of the grid
+/// overflows. Running out of memory aborts the process like any other failed allocation in Rust.
+#[no_mangle]
+pub extern "C" fn lgca_create(height: usize) -> *mut LgcaSimulation {
+    if height < 2 {
+        return std::ptr::null_mut();
+    }
+    catch_unwind(|| Box::into_raw(Box::new(LgcaSimulation(Simulation::new(height, None)))))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// Reset the particles, the round counter and the timings and set the simulation up from a scenario string
+/// like `box=500 noise=0.04 body_force=0.001`
+///
+/// # Safety
+/// `simulation` must come from `lgca_create` and `scenario` must be a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_configure(
+    simulation: *mut LgcaSimulation,
+    scenario: *const c_char,
+) -> c_int {
+    let (Some(simulation), false) = (simulation.as_mut(), scenario.is_null()) else {
+        return LGCA_ERROR_ARGUMENT;
+    };
+    let Ok(scenario) = CStr::from_ptr(scenario).to_str() else {
+        return LGCA_ERROR_SCENARIO;
+    };
+    match scenario.parse::<Scenario>() {
+        Ok(scenario) => catch_panic(|| {
+            scenario.apply(&mut simulation.0);
+            0
+        }),
+        Err(error) => {
+            eprintln!("Invalid scenario: {}", error);
+            LGCA_ERROR_SCENARIO
+        }
+    }
+}
+
+/// Calculate `rounds` rounds
+///
+/// # Safety
+/// `simulation` must come from `lgca_create`.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_step(simulation: *mut LgcaSimulation, rounds: usize) -> c_int {
+    let Some(simulation) = simulation.as_mut() else {
+        return LGCA_ERROR_ARGUMENT;
+    };
+    catch_panic(|| {
+        for _ in 0..rounds {
+            simulation.0.step();
+        }
+        0
+    })
+}
+
+/// Number of rounds calculated so far
+///
+/// # Safety
+/// `simulation` must come from `lgca_create`.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_round(simulation: *const LgcaSimulation) -> usize {
+    simulation
+        .as_ref()
+        .map_or(0, |simulation| simulation.0.round())
+}
+
+/// Number of particles in the grid
+///
+/// # Safety
+/// `simulation` must come from `lgca_create`.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_particles(simulation: *const LgcaSimulation) -> u64 {
+    simulation
+        .as_ref()
+        .map_or(0, |simulation| simulation.0.particles())
+}
+
+/// Write the number of blocks in a row and the number of rows of blocks for `block_size`
+///
+/// # Safety
+/// `simulation` must come from `lgca_create`, `width` and `height` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_fields_size(
+    simulation: *const LgcaSimulation,
+    block_size: usize,
+    width: *mut usize,
+    height: *mut usize,
+) -> c_int {
+    let (Some(simulation), Some(width), Some(height)) =
+        (simulation.as_ref(), width.as_mut(), height.as_mut())
+    else {
+        return LGCA_ERROR_ARGUMENT;
+    };
+    if block_size == 0 {
+        return LGCA_ERROR_ARGUMENT;
+    }
+    *width = WIDTH.div_ceil(block_size);
+    *height = simulation.0.height().div_ceil(block_size);
+    0
+}
+
+/// Write the density and velocity averaged over blocks of `block_size` cells, row by row.
+///
+/// Every buffer must hold `length` floats, at least as many as there are blocks.
+/// Returns the number of blocks written. If that does not fit into an int, nothing is written and
+/// `LGCA_ERROR_ARGUMENT` is returned, a larger `block_size` helps.
+///
+/// # Safety
+/// `simulation` must come from `lgca_create`, the buffers must be valid for `length` floats.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_read_fields(
+    simulation: *const LgcaSimulation,
+    block_size: usize,
+    density: *mut f32,
+    velocity_x: *mut f32,
+    velocity_y: *mut f32,
+    length: usize,
+) -> c_int {
+    let Some(simulation) = simulation.as_ref() else {
+        return LGCA_ERROR_ARGUMENT;
+    };
+    if block_size == 0 || density.is_null() || velocity_x.is_null() || velocity_y.is_null() {
+        return LGCA_ERROR_ARGUMENT;
+    }
+    catch_panic(|| {
+        let fields = simulation.0.coarse_fields(block_size);
+        let blocks = fields.density.len();
+        let Ok(written) = c_int::try_from(blocks) else {
+            return LGCA_ERROR_ARGUMENT;
+        };
+        if length < blocks {
+            return LGCA_ERROR_BUFFER_SIZE;
+        }
+        std::slice::from_raw_parts_mut(density, blocks).copy_from_slice(&fields.density);
+        std::slice::from_raw_parts_mut(velocity_x, blocks).copy_from_slice(&fields.velocity_x);
+        std::slice::from_raw_parts_mut(velocity_y, blocks).copy_from_slice(&fields.velocity_y);
+        written
+    })
+}
+
+/// Free a simulation. Passing null does nothing
+///
+/// # Safety
+/// `simulation` must come from `lgca_create` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn lgca_destroy(simulation: *mut LgcaSimulation) {
+    if !simulation.is_null() {
+        drop(Box::from_raw(simulation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drive_simulation_through_c_interface() {
+        assert!(lgca_create(1).is_null());
+        let simulation = lgca_create(10);
+        unsafe {
+            assert_eq!(lgca_configure(simulation, c"box=4 noise=0".as_ptr()), 0);
+            assert_eq!(
+                lgca_configure(simulation, c"box=four".as_ptr()),
+                LGCA_ERROR_SCENARIO
+            );
+            assert_eq!(lgca_configure(simulation, c"box=4".as_ptr()), 0);
+            assert_eq!(lgca_particles(simulation), 4 * 4 * 6);
+            assert_eq!(lgca_step(simulation, 5), 0);
+            assert_eq!(lgca_round(simulation), 5);
+
+            let (mut width, mut height) = (0, 0);
+            assert_eq!(lgca_fields_size(simulation, 5, &mut width, &mut height), 0);
+            assert_eq!(height, 2);
+            let blocks = width * height;
+            let mut density = vec![0.0; blocks];
+            let mut velocity_x = vec![0.0; blocks];
+            let mut velocity_y = vec![0.0; blocks];
+            assert_eq!(
+                lgca_read_fields(
+                    simulation,
+                    5,
+                    density.as_mut_ptr(),
+                    velocity_x.as_mut_ptr(),
+                    velocity_y.as_mut_ptr(),
+                    blocks - 1
+                ),
+                LGCA_ERROR_BUFFER_SIZE
+            );
+            assert_eq!(
+                lgca_read_fields(
+                    simulation,
+                    5,
+                    density.as_mut_ptr(),
+                    velocity_x.as_mut_ptr(),
+                    velocity_y.as_mut_ptr(),
+                    blocks
+                ),
+                blocks as c_int
+            );
+            let particles: f32 = density.iter().map(|density| density * 25.0 * 6.0).sum();
+            assert!((particles - (4 * 4 * 6) as f32).abs() < 0.01);
+
+            assert_eq!(lgca_configure(simulation, c"box=2".as_ptr()), 0);
+            assert_eq!(lgca_round(simulation), 0);
+            assert_eq!((*simulation).0.timings.calculation(), Default::default());
+            lgca_destroy(simulation);
+        }
+    }
+
+    #[test]
+    fn panics_become_an_error_code() {
+        assert_eq!(catch_panic(|| 1), 1);
+        assert_eq!(
+            catch_panic(|| panic!("the host must not see this")),
+            LGCA_ERROR_PANIC
+        );
+    }
+}
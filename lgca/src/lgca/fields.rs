@@ -0,0 +1,85 @@
+use super::Cell;
+
+/// Density and velocity averaged over square blocks of cells.
+///
+/// The raw cells are way too noisy to be useful on their own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoarseFields {
+    /// Number of blocks in a row
+    pub width: usize,
+    /// Number of rows of blocks
+    pub height: usize,
+    pub block_size: usize,
+    /// Fraction of occupied channels in every block, row by row
+    pub density: Vec<f32>,
+    /// Mean momentum per cell in every block, row by row
+    pub velocity_x: Vec<f32>,
+    pub velocity_y: Vec<f32>,
+}
+
+impl CoarseFields {
+    /// Average the grid over blocks. Blocks at the east and south borders may be smaller
+    pub fn from_grid<const WIDTH: usize>(grid: &[[Cell; WIDTH]], block_size: usize) -> Self {
+        assert!(block_size > 0, "Blocks must contain at least one cell");
+        let width = WIDTH.div_ceil(block_size);
+        let height = grid.len().div_ceil(block_size);
+        let mut particles = vec![0u32; width * height];
+        let mut cells = vec![0u32; width * height];
+        let mut velocity_x = vec![0f32; width * height];
+        let mut velocity_y = vec![0f32; width * height];
+
+        for (y, row) in grid.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let index = (y / block_size) * width + x / block_size;
+                let (momentum_x, momentum_y) = cell.get_momentum();
+                particles[index] += cell.get_particles() as u32;
+                cells[index] += 1;
+                velocity_x[index] += momentum_x;
+                velocity_y[index] += momentum_y;
+            }
+        }
+
+        let density = particles
+            .iter()
+            .zip(cells.iter())
+            .map(|(particles, cells)| *particles as f32 / (*cells * 6) as f32)
+            .collect();
+        for ((x, y), cells) in velocity_x
+            .iter_mut()
+            .zip(velocity_y.iter_mut())
+            .zip(cells.iter())
+        {
+            *x /= *cells as f32;
+            *y /= *cells as f32;
+        }
+
+        Self {
+            width,
+            height,
+            block_size,
+            density,
+            velocity_x,
+            velocity_y,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_at_the_border_are_smaller() {
+        let mut grid = [[Cell::new(); 5]; 3];
+        grid[2][4].raw = 0b00111111;
+        grid[0][0].set_to_east(true);
+
+        let fields = CoarseFields::from_grid(&grid, 2);
+
+        assert_eq!((fields.width, fields.height), (3, 2));
+        assert_eq!(fields.density[0], 1.0 / 24.0);
+        assert_eq!(fields.velocity_x[0], 0.25);
+        assert_eq!(fields.density[5], 1.0);
+        assert!(fields.velocity_x[5].abs() < 1e-6);
+    }
+}
@@ -0,0 +1,123 @@
+use rand::prelude::*;
+use rayon::prelude::*;
+
+use super::{
+    cell::{RNG, TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST, TO_WEST},
+    Cell,
+};
+
+/// Pairs of directions that can be flipped to push a particle to the east.
+///
+/// The first flip is preferred, because it adds the most momentum.
+const FLIPS: [(u8, u8); 3] = [
+    (TO_WEST, TO_EAST),
+    (TO_NORTH_WEST, TO_NORTH_EAST),
+    (TO_SOUTH_WEST, TO_SOUTH_EAST),
+];
+
+/// Pushes particles to the east to drive a flow, like a pressure gradient would.
+///
+/// Every affected cell has one particle flipped from a western to the matching eastern direction.
+/// The number of particles stays the same.
+#[derive(Copy, Clone, Debug)]
+pub struct BodyForce {
+    /// Probability that a cell gets flipped in a round
+    pub probability: f64,
+}
+
+impl BodyForce {
+    pub fn new(probability: f64) -> Self {
+        Self { probability }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.probability > 0.0
+    }
+
+    /// Flip a particle to the east if the configuration of the cell allows it.
+    ///
+    /// Returns the x momentum that was added.
+    pub fn flip(cell: &mut Cell) -> f32 {
+        for (from, to) in FLIPS {
+            if cell.raw & from != 0 && cell.raw & to == 0 {
+                cell.raw ^= from | to;
+                return if from == TO_WEST { 2.0 } else { 1.0 };
+            }
+        }
+        0.0
+    }
+
+    /// Apply the force to every cell of the grid
+    pub fn apply<const WIDTH: usize>(&self, grid: &mut [[Cell; WIDTH]]) {
+        if !self.is_active() {
+            return;
+        }
+        grid.par_iter_mut().for_each(|row| self.apply_row(row));
+    }
+
+    /// Apply the force to every cell of a single row
+    pub fn apply_row(&self, row: &mut [Cell]) {
+        if !self.is_active() {
+            return;
+        }
+        RNG.with(|random| {
+            let random = &mut *random.borrow_mut();
+            for cell in row.iter_mut() {
+                if random.gen_bool(self.probability) {
+                    BodyForce::flip(cell);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flip_prefers_west_to_east() {
+        let mut cell = Cell {
+            raw: TO_WEST | TO_NORTH_WEST,
+        };
+        assert_eq!(BodyForce::flip(&mut cell), 2.0);
+        assert_eq!(cell.raw, TO_EAST | TO_NORTH_WEST);
+        assert_eq!(BodyForce::flip(&mut cell), 1.0);
+        assert_eq!(cell.raw, TO_EAST | TO_NORTH_EAST);
+        assert_eq!(BodyForce::flip(&mut cell), 0.0);
+    }
+
+    #[test]
+    fn force_keeps_particles_and_adds_momentum() {
+        const WIDTH: usize = 20;
+        let mut grid = [[Cell::new(); WIDTH]; 10];
+        for (index, cell) in grid.iter_mut().flatten().enumerate() {
+            cell.raw = (index * 13 % 64) as u8;
+        }
+        let particles = |grid: &[[Cell; WIDTH]]| -> u32 {
+            grid.iter()
+                .flatten()
+                .map(|cell| cell.get_particles() as u32)
+                .sum()
+        };
+        let momentum = |grid: &[[Cell; WIDTH]]| -> i32 {
+            grid.iter()
+                .flatten()
+                .map(|cell| {
+                    2 * cell.to_east() as i32 - 2 * cell.to_west() as i32
+                        + cell.to_north_east() as i32
+                        + cell.to_south_east() as i32
+                        - cell.to_north_west() as i32
+                        - cell.to_south_west() as i32
+                })
+                .sum()
+        };
+        let particles_before = particles(&grid);
+        let momentum_before = momentum(&grid);
+
+        BodyForce::new(1.0).apply(&mut grid);
+
+        assert_eq!(particles(&grid), particles_before);
+        assert!(momentum(&grid) > momentum_before);
+    }
+}
@@ -0,0 +1,245 @@
+//! Initial conditions painted as an image.
+//!
+//! The brightness of a pixel is the probability that a channel is occupied. The hue is the direction of the
+//! mean flow, red points east and the colors turn counterclockwise through the directions of the lattice.
+//! The saturation is the speed, fully saturated pixels move with `MAX_SPEED`.
+use rand::prelude::*;
+use rayon::prelude::*;
+use ril::{Image, ImageFormat, Rgb};
+use std::{fs::File, io::BufReader, ops::Range, path::Path};
+
+use super::{
+    lattice::{Fhp, LatticeModel},
+    simulation::Simulation,
+};
+
+/// Speed of a fully saturated pixel. Faster flows would need negative occupations in the opposite direction
+pub const MAX_SPEED: f64 = 0.5;
+
+/// Density per channel and mean velocity of a pixel. The velocity points south for positive y, like the rows
+pub fn pixel_to_flow(pixel: &Rgb) -> (f64, (f64, f64)) {
+    let (r, g, b) = (pixel.r as f64, pixel.g as f64, pixel.b as f64);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max == 0.0 {
+        return (0.0, (0.0, 0.0));
+    }
+    let chroma = max - min;
+    let hue = if chroma == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    let speed = MAX_SPEED * chroma / max;
+    let angle = hue.to_radians();
+    (max / 255.0, (speed * angle.cos(), -speed * angle.sin()))
+}
+
+/// Rows of an image with `image_height` rows that the rows of this rank are sampled from
+fn image_rows<const WIDTH: usize>(
+    simulation: &Simulation<WIDTH>,
+    image_height: usize,
+) -> Range<usize> {
+    let global_height = simulation.global_height();
+    let first_row = simulation.row_offset();
+    let last_row = first_row + simulation.height() - 1;
+    first_row * image_height / global_height..last_row * image_height / global_height + 1
+}
+
+/// Replace the rows of this rank with particles sampled from the flows of the image rows `rows`.
+///
+/// The image is stretched over the whole grid. Every row gets its own random numbers from `seed`,
+/// so the result does not depend on the number of ranks.
+fn initialize_from_flows<const WIDTH: usize>(
+    simulation: &mut Simulation<WIDTH>,
+    image_width: usize,
+    image_height: usize,
+    rows: Range<usize>,
+    flows: &[Vec<(f64, (f64, f64))>],
+    seed: u64,
+) {
+    let global_height = simulation.global_height();
+    let row_offset = simulation.row_offset();
+    simulation
+        .grid_mut()
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(y, row)| {
+            let global_y = row_offset + y;
+            let random = &mut SmallRng::seed_from_u64(
+                seed ^ (global_y as u64).wrapping_mul(0x9E3779B97F4A7C15),
+            );
+            let image_y = global_y * image_height / global_height;
+            let flows = &flows[image_y - rows.start];
+            for (x, cell) in row.iter_mut().enumerate() {
+                let (density, (u_x, u_y)) = flows[x * image_width / WIDTH];
+                cell.raw = 0;
+                for direction in Fhp::DIRECTIONS {
+                    let (c_x, c_y) = direction.velocity;
+                    // Linearized equilibrium d * (1 + 2 * c_i * u)
+                    let probability =
+                        (density * (1.0 + 2.0 * (c_x * u_x + c_y * u_y))).clamp(0.0, 1.0);
+                    if random.gen_bool(probability) {
+                        cell.raw |= direction.bit;
+                    }
+                }
+            }
+        });
+}
+
+/// Replace the rows of this rank with particles sampled from `image`, see `initialize_from_flows`
+pub fn initialize_from_image<const WIDTH: usize>(
+    simulation: &mut Simulation<WIDTH>,
+    image: &Image<Rgb>,
+    seed: u64,
+) {
+    let (image_width, image_height) = (image.width() as usize, image.height() as usize);
+    let rows = image_rows(simulation, image_height);
+    let flows: Vec<Vec<_>> = rows
+        .clone()
+        .map(|y| {
+            (0..image.width())
+                .map(|x| pixel_to_flow(image.pixel(x, y as u32)))
+                .collect()
+        })
+        .collect();
+    initialize_from_flows(simulation, image_width, image_height, rows, &flows, seed);
+}
+
+/// Read a PNG and initialize the rows of this rank from it.
+///
+/// The rows are decoded one by one and the decoder stops after the last row this rank samples.
+/// Interlaced PNGs store the rows out of order, they are decoded as a whole.
+pub fn read_initial_image<const WIDTH: usize>(
+    simulation: &mut Simulation<WIDTH>,
+    path: &Path,
+    seed: u64,
+) {
+    let open = || {
+        File::open(path)
+            .unwrap_or_else(|error| panic!("Can not open {}: {}", path.display(), error))
+    };
+    let mut decoder = png::Decoder::new(BufReader::new(open()));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .unwrap_or_else(|error| panic!("Can not decode {}: {}", path.display(), error));
+    let info = reader.info();
+    let (image_width, image_height) = (info.width as usize, info.height as usize);
+    if info.interlaced {
+        let image = Image::<Rgb>::from_reader(ImageFormat::Png, open())
+            .unwrap_or_else(|error| panic!("Can not decode {}: {}", path.display(), error));
+        initialize_from_image(simulation, &image, seed);
+        return;
+    }
+
+    let samples = reader.output_color_type().0.samples();
+    let rows = image_rows(simulation, image_height);
+    let mut flows = Vec::with_capacity(rows.len());
+    for y in 0..rows.end {
+        let row = reader
+            .next_row()
+            .unwrap_or_else(|error| panic!("Can not decode {}: {}", path.display(), error))
+            .unwrap_or_else(|| panic!("{} ends before row {}", path.display(), y));
+        if y < rows.start {
+            continue;
+        }
+        // Gray images have one sample per pixel, alpha is ignored
+        let flow = row
+            .data()
+            .chunks_exact(samples)
+            .map(|pixel| match pixel {
+                [r, g, b, ..] => pixel_to_flow(&Rgb::new(*r, *g, *b)),
+                [gray, ..] => pixel_to_flow(&Rgb::new(*gray, *gray, *gray)),
+                [] => unreachable!(),
+            })
+            .collect();
+        flows.push(flow);
+    }
+    initialize_from_flows(simulation, image_width, image_height, rows, &flows, seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::testing::TempDir;
+
+    #[test]
+    fn hue_sets_the_direction() {
+        let (density, velocity) = pixel_to_flow(&Rgb::new(100, 100, 100));
+        assert!((density - 100.0 / 255.0).abs() < 1e-12);
+        assert_eq!(velocity, (0.0, 0.0));
+
+        let (density, (u_x, u_y)) = pixel_to_flow(&Rgb::new(255, 0, 0));
+        assert_eq!(density, 1.0);
+        assert!((u_x - MAX_SPEED).abs() < 1e-12 && u_y.abs() < 1e-12);
+
+        // Green is 120 degrees counterclockwise, towards the north west
+        let (_, (u_x, u_y)) = pixel_to_flow(&Rgb::new(0, 255, 0));
+        assert!((u_x + 0.5 * MAX_SPEED).abs() < 1e-12);
+        assert!((u_y + 0.75f64.sqrt() * MAX_SPEED).abs() < 1e-12);
+    }
+
+    #[test]
+    fn particles_follow_the_image() {
+        // Left half empty, right half a dim red flow to the east
+        let mut image = Image::new(2, 1, Rgb::black());
+        image.set_pixel(1, 0, Rgb::new(64, 32, 32));
+        let mut simulation = Simulation::<64>::new(64, None);
+        initialize_from_image(&mut simulation, &image, 7);
+
+        let grid = simulation.grid();
+        assert!(grid
+            .iter()
+            .all(|row| row[..32].iter().all(|cell| cell.raw == 0)));
+        let cells = (32 * 64) as f64;
+        let occupation = |bit: u8| {
+            grid.iter()
+                .flat_map(|row| &row[32..])
+                .filter(|cell| cell.raw & bit != 0)
+                .count() as f64
+                / cells
+        };
+        let (density, (u_x, _)) = pixel_to_flow(&Rgb::new(64, 32, 32));
+        for direction in Fhp::DIRECTIONS {
+            let expected = density * (1.0 + 2.0 * direction.velocity.0 * u_x);
+            assert!(
+                (occupation(direction.bit) - expected).abs() < 0.03,
+                "{} != {}",
+                occupation(direction.bit),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn streamed_rows_match_the_decoded_image() {
+        let (width, height) = (5u32, 7u32);
+        let data: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 37 % 256) as u8)
+            .collect();
+        let directory = TempDir::new("init_image");
+        let path = directory.join("initial.png");
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&data)
+            .unwrap();
+
+        let mut streamed = Simulation::<16>::new(20, None);
+        read_initial_image(&mut streamed, &path, 3);
+        let image =
+            Image::<Rgb>::from_reader(ImageFormat::Png, File::open(&path).unwrap()).unwrap();
+        let mut decoded = Simulation::<16>::new(20, None);
+        initialize_from_image(&mut decoded, &image, 3);
+        assert_eq!(streamed.grid(), decoded.grid());
+        assert!(streamed.particles() > 0);
+    }
+}
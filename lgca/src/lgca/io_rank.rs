@@ -0,0 +1,316 @@
+//! A dedicated rank for rendering and writing the animation.
+//!
+//! The compute ranks only sum their rows over blocks and hand the coarse strips to the last rank without
+//! waiting for it. The I/O rank adds up and colors the blocks and encodes all frames into a single WebP, so the
+//! compute ranks never spend time on drawing or encoding.
+use mpi::{
+    request::{Request, StaticScope},
+    topology::{Color, SimpleCommunicator},
+    traits::*,
+    Tag,
+};
+use ril::{Image, ResizeAlgorithm, Rgb};
+use std::{path::Path, time::Duration};
+
+use super::{
+    output::Output,
+    simulation::Simulation,
+    visualization::{flow_to_color, save_webp},
+    Cell,
+};
+
+/// Tag of the messages with coarse strips. An empty strip ends the animation
+const STRIP: Tag = 2;
+
+/// Split the world into the compute ranks and the last rank, which does the I/O.
+///
+/// Returns the communicator of the compute ranks, or `None` on the I/O rank. Collective
+pub fn split_io_rank(world: &SimpleCommunicator) -> Option<SimpleCommunicator> {
+    assert!(
+        world.size() > 1,
+        "A dedicated I/O rank needs at least one more rank for the simulation"
+    );
+    let color = if world.rank() == world.size() - 1 {
+        Color::undefined()
+    } else {
+        Color::with_value(0)
+    };
+    world.split_by_color(color)
+}
+
+/// Blocks of this many cells in each direction become one pixel, so the strips are about as big as the images
+fn block_size(scaling: f64) -> usize {
+    (1.0 / scaling).round().max(1.0) as usize
+}
+
+/// Sums of particles, momentum and cells over the blocks that overlap the rows of a strip.
+///
+/// The blocks are aligned to the global rows, so a block at the border between two ranks gets a part from both.
+/// The first value is the index of the first block row, followed by the four sums of all blocks one after another.
+pub fn block_sums<const WIDTH: usize>(
+    grid: &[[Cell; WIDTH]],
+    row_offset: usize,
+    block_size: usize,
+) -> Vec<f32> {
+    let width = WIDTH.div_ceil(block_size);
+    let first_block_row = row_offset / block_size;
+    let block_rows = (row_offset + grid.len() - 1) / block_size - first_block_row + 1;
+    let blocks = width * block_rows;
+    let mut values = vec![0f32; 1 + 4 * blocks];
+    values[0] = first_block_row as f32;
+    for (y, row) in grid.iter().enumerate() {
+        let block_row = (row_offset + y) / block_size - first_block_row;
+        for (x, cell) in row.iter().enumerate() {
+            let index = 1 + block_row * width + x / block_size;
+            let (momentum_x, momentum_y) = cell.get_momentum();
+            values[index] += cell.get_particles() as f32;
+            values[index + blocks] += momentum_x;
+            values[index + 2 * blocks] += momentum_y;
+            values[index + 3 * blocks] += 1.0;
+        }
+    }
+    values
+}
+
+/// Add the block sums of a strip to the sums of all blocks of a frame, which has `width` blocks per row
+fn add_strip(frame: &mut Vec<[f32; 4]>, values: &[f32], width: usize) {
+    let blocks = (values.len() - 1) / 4;
+    let first_block = values[0] as usize * width;
+    if frame.len() < first_block + blocks {
+        frame.resize(first_block + blocks, [0.0; 4]);
+    }
+    for (block, sums) in frame[first_block..first_block + blocks]
+        .iter_mut()
+        .enumerate()
+    {
+        for (sum, plane) in sums.iter_mut().zip(0..4) {
+            *sum += values[1 + plane * blocks + block];
+        }
+    }
+}
+
+/// A strip that is still on its way to the I/O rank
+struct PendingStrip {
+    request: Request<'static, [f32], StaticScope>,
+    /// MPI reads the values until the request is complete, so they must live at least as long
+    _values: Box<[f32]>,
+}
+
+/// Sends the coarse fields of the rows of this rank to the I/O rank at the framerate of `WebPRenderer`.
+///
+/// At most one strip is in flight, the next frame only waits for the previous one to arrive.
+pub struct StripSender {
+    world: SimpleCommunicator,
+    block_size: usize,
+    time_per_round: Duration,
+    time_per_frame: Duration,
+    gif_time: Duration,
+    frames: usize,
+    pending: Option<PendingStrip>,
+}
+
+impl StripSender {
+    pub fn new(
+        world: SimpleCommunicator,
+        rounds_per_second: usize,
+        frames_per_second: usize,
+        scaling: f64,
+    ) -> Self {
+        Self {
+            world,
+            block_size: block_size(scaling),
+            time_per_round: Duration::from_secs_f64(1.0 / rounds_per_second as f64),
+            time_per_frame: Duration::from_secs_f64(1.0 / (frames_per_second as f64).max(1.0)),
+            gif_time: Duration::new(0, 0),
+            frames: 0,
+            pending: None,
+        }
+    }
+
+    /// Number of frames sent so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Wait until the strip in flight arrived
+    fn wait(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.request.wait();
+        }
+    }
+
+    fn send(&mut self, values: Vec<f32>) {
+        self.wait();
+        let values = values.into_boxed_slice();
+        // The heap allocation does not move with the box, which is kept in `pending` until the send is complete
+        let in_flight: &'static [f32] = unsafe { &*(&*values as *const [f32]) };
+        let io_rank = self.world.size() - 1;
+        let request = self.world.process_at_rank(io_rank).immediate_send_with_tag(
+            StaticScope,
+            in_flight,
+            STRIP,
+        );
+        self.pending = Some(PendingStrip {
+            request,
+            _values: values,
+        });
+    }
+
+    fn send_frame<const WIDTH: usize>(&mut self, simulation: &Simulation<WIDTH>) {
+        self.send(block_sums(
+            simulation.grid(),
+            simulation.row_offset(),
+            self.block_size,
+        ));
+        self.frames += 1;
+    }
+}
+
+impl Drop for StripSender {
+    fn drop(&mut self) {
+        self.wait();
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for StripSender {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        if simulation.round() == 0 {
+            self.send_frame(simulation);
+            return;
+        }
+        self.gif_time += self.time_per_round;
+        while self.gif_time >= self.time_per_frame {
+            self.gif_time -= self.time_per_frame;
+            self.send_frame(simulation);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.send(Vec::new());
+        self.wait();
+    }
+}
+
+/// Runs on the I/O rank and turns the strips of all compute ranks into one animation
+pub struct StripReceiver {
+    world: SimpleCommunicator,
+    block_size: usize,
+    scaling: f64,
+    time_per_frame: Duration,
+}
+
+impl StripReceiver {
+    pub fn new(world: SimpleCommunicator, frames_per_second: usize, scaling: f64) -> Self {
+        Self {
+            world,
+            block_size: block_size(scaling),
+            scaling,
+            time_per_frame: Duration::from_secs_f64(1.0 / (frames_per_second as f64).max(1.0)),
+        }
+    }
+
+    /// Color the sums of particles, momentum and cells of every block, one pixel per block
+    pub fn color_blocks(frame: &[[f32; 4]]) -> Vec<Rgb> {
+        frame
+            .iter()
+            .map(|[particles, momentum_x, momentum_y, cells]| {
+                let (velocity_x, velocity_y) = (momentum_x / cells, momentum_y / cells);
+                let angle = velocity_y.atan2(velocity_x) / std::f32::consts::PI * 2.0;
+                let speed = (velocity_x * velocity_x + velocity_y * velocity_y).sqrt();
+                flow_to_color(angle, speed as f64, (particles / (cells * 6.0)) as f64)
+            })
+            .collect()
+    }
+
+    /// Receive frames until the compute ranks are done and write them into `path`. Returns the number of frames
+    pub fn run<const WIDTH: usize>(&self, path: &Path) -> usize {
+        let compute_ranks = self.world.size() - 1;
+        let width = WIDTH.div_ceil(self.block_size);
+        let mut images = Vec::new();
+        loop {
+            // Every rank sends its strips in order, so frame by frame the strips can be taken from rank after rank
+            let mut frame = Vec::new();
+            let mut done = false;
+            for rank in 0..compute_ranks {
+                let (values, _) = self
+                    .world
+                    .process_at_rank(rank)
+                    .receive_vec_with_tag::<f32>(STRIP);
+                if values.is_empty() {
+                    done = true;
+                } else {
+                    add_strip(&mut frame, &values, width);
+                }
+            }
+            if done {
+                break;
+            }
+            let pixels = StripReceiver::color_blocks(&frame);
+            let height = pixels.len() / width;
+            let scale = self.block_size as f64 * self.scaling;
+            images.push(Image::from_pixels(width as u32, pixels).resized(
+                (width as f64 * scale).round() as u32,
+                (height as f64 * scale).round() as u32,
+                ResizeAlgorithm::Lanczos3,
+            ));
+        }
+        let frames = images.len();
+        save_webp(images, self.time_per_frame, path.to_str().unwrap());
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{cell::TO_EAST, fields::CoarseFields, visualization::cells_to_color};
+
+    #[test]
+    fn strips_get_the_colors_of_the_renderer() {
+        let mut grid = [[Cell::new(); 4]; 2];
+        grid[0][0].raw = TO_EAST;
+        grid[1][3].raw = 0b00111111;
+        grid[1][2].raw = 0b00011011;
+        let mut frame = Vec::new();
+        add_strip(&mut frame, &block_sums(&grid, 0, 1), 4);
+
+        let colors = StripReceiver::color_blocks(&frame);
+        let expected: Vec<Rgb> = grid
+            .iter()
+            .flatten()
+            .map(|cell| cells_to_color(&[cell]))
+            .collect();
+        assert_eq!(colors.len(), expected.len());
+        for (color, expected) in colors.iter().zip(expected) {
+            for (a, b) in [
+                (color.r, expected.r),
+                (color.g, expected.g),
+                (color.b, expected.b),
+            ] {
+                assert!(a.abs_diff(b) <= 1, "{:?} != {:?}", color, expected);
+            }
+        }
+        assert_eq!(block_size(0.25), 4);
+        assert_eq!(block_size(2.0), 1);
+    }
+
+    #[test]
+    fn blocks_are_aligned_to_the_global_rows() {
+        let mut grid = [[Cell::new(); 5]; 7];
+        for (index, cell) in grid.iter_mut().flatten().enumerate() {
+            cell.raw = (index * 13 % 64) as u8;
+        }
+        // Strips of 3 and 4 rows split the second row of blocks
+        let mut frame = Vec::new();
+        add_strip(&mut frame, &block_sums(&grid[..3], 0, 2), 3);
+        add_strip(&mut frame, &block_sums(&grid[3..], 3, 2), 3);
+
+        let fields = CoarseFields::from_grid(&grid, 2);
+        assert_eq!(frame.len(), fields.width * fields.height);
+        for (block, [particles, momentum_x, momentum_y, cells]) in frame.iter().enumerate() {
+            assert!((particles / (cells * 6.0) - fields.density[block]).abs() < 1e-6);
+            assert!((momentum_x / cells - fields.velocity_x[block]).abs() < 1e-5);
+            assert!((momentum_y / cells - fields.velocity_y[block]).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use rayon::prelude::*;
+
+use super::{
+    cell::{TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST, TO_WEST},
+    Cell,
+};
+
+/// One direction a particle can move in
+pub struct Direction {
+    /// Bit of the direction in `Cell::raw`
+    pub bit: u8,
+    /// Unit vector of the velocity. y points south, like the rows
+    pub velocity: (f64, f64),
+    /// Index of the opposite direction
+    pub opposite: usize,
+}
+
+/// Geometry and collision rules of a lattice gas.
+///
+/// Only the periodic reference path `lattice_round` is generic over the model, so the models can be compared in
+/// the experiments. `Simulation` with its optimized kernels in `new_movements`, the boundaries, MPI and the
+/// command line are FHP only and do not go through this trait. `Fhp` describes them with the neighbor offsets
+/// the boundaries use and the collisions of `Cell`.
+pub trait LatticeModel: Sync {
+    const NAME: &'static str;
+
+    const DIRECTIONS: &'static [Direction];
+
+    /// Neighbor offsets repeat after this many rows. Heights of periodic grids must be a multiple of it
+    const ROW_PERIOD: usize;
+
+    /// Vertical distance between two rows
+    const ROW_DISTANCE: f64;
+
+    /// Offset to the neighbor in a direction, for a cell in row `y`
+    fn neighbor_offset(direction: usize, y: usize) -> (isize, isize);
+
+    /// State of a cell after the collision. Randomized rules draw from the thread local `RNG`
+    fn collide(raw: u8) -> u8;
+
+    /// Position of the center of a cell
+    fn position(x: usize, y: usize) -> (f64, f64) {
+        (x as f64, y as f64 * Self::ROW_DISTANCE)
+    }
+}
+
+/// The hexagonal FHP lattice with the collisions from `Cell::process_collision`
+pub struct Fhp;
+
+/// Offsets to the neighbor in every direction of `Fhp::DIRECTIONS` for even rows.
+/// Even rows are shifted half a cell to the east
+pub const FHP_EVEN_ROW_OFFSETS: [(isize, isize); 6] =
+    [(1, 0), (1, -1), (0, -1), (-1, 0), (0, 1), (1, 1)];
+pub const FHP_ODD_ROW_OFFSETS: [(isize, isize); 6] =
+    [(1, 0), (0, -1), (-1, -1), (-1, 0), (-1, 1), (0, 1)];
+
+impl LatticeModel for Fhp {
+    const NAME: &'static str = "fhp";
+
+    const DIRECTIONS: &'static [Direction] = &[
+        Direction {
+            bit: TO_EAST,
+            velocity: (1.0, 0.0),
+            opposite: 3,
+        },
+        Direction {
+            bit: TO_NORTH_EAST,
+            velocity: (0.5, -0.866_025_403_784_438_6),
+            opposite: 4,
+        },
+        Direction {
+            bit: TO_NORTH_WEST,
+            velocity: (-0.5, -0.866_025_403_784_438_6),
+            opposite: 5,
+        },
+        Direction {
+            bit: TO_WEST,
+            velocity: (-1.0, 0.0),
+            opposite: 0,
+        },
+        Direction {
+            bit: TO_SOUTH_WEST,
+            velocity: (-0.5, 0.866_025_403_784_438_6),
+            opposite: 1,
+        },
+        Direction {
+            bit: TO_SOUTH_EAST,
+            velocity: (0.5, 0.866_025_403_784_438_6),
+            opposite: 2,
+        },
+    ];
+
+    const ROW_PERIOD: usize = 2;
+
+    const ROW_DISTANCE: f64 = 0.866_025_403_784_438_6;
+
+    fn neighbor_offset(direction: usize, y: usize) -> (isize, isize) {
+        if y % 2 == 0 {
+            FHP_EVEN_ROW_OFFSETS[direction]
+        } else {
+            FHP_ODD_ROW_OFFSETS[direction]
+        }
+    }
+
+    fn collide(raw: u8) -> u8 {
+        let mut cell = Cell { raw };
+        cell.process_collision();
+        cell.raw
+    }
+
+    fn position(x: usize, y: usize) -> (f64, f64) {
+        let shift = if y % 2 == 0 { 0.5 } else { 0.0 };
+        (x as f64 + shift, y as f64 * Self::ROW_DISTANCE)
+    }
+}
+
+pub const HPP_EAST: u8 = 0b0001;
+pub const HPP_NORTH: u8 = 0b0010;
+pub const HPP_WEST: u8 = 0b0100;
+pub const HPP_SOUTH: u8 = 0b1000;
+
+/// The square HPP lattice. Only head-on collisions, which turn the pair by 90 degrees.
+///
+/// It only runs on periodic grids through `lattice_round`, not in `Simulation`
+pub struct Hpp;
+
+static HPP_COLLISIONS: [u8; 16] = {
+    let mut table = [0; 16];
+    let mut state = 0;
+    while state < 16 {
+        table[state] = state as u8;
+        state += 1;
+    }
+    table[(HPP_EAST | HPP_WEST) as usize] = HPP_NORTH | HPP_SOUTH;
+    table[(HPP_NORTH | HPP_SOUTH) as usize] = HPP_EAST | HPP_WEST;
+    table
+};
+
+impl LatticeModel for Hpp {
+    const NAME: &'static str = "hpp";
+
+    const DIRECTIONS: &'static [Direction] = &[
+        Direction {
+            bit: HPP_EAST,
+            velocity: (1.0, 0.0),
+            opposite: 2,
+        },
+        Direction {
+            bit: HPP_NORTH,
+            velocity: (0.0, -1.0),
+            opposite: 3,
+        },
+        Direction {
+            bit: HPP_WEST,
+            velocity: (-1.0, 0.0),
+            opposite: 0,
+        },
+        Direction {
+            bit: HPP_SOUTH,
+            velocity: (0.0, 1.0),
+            opposite: 1,
+        },
+    ];
+
+    const ROW_PERIOD: usize = 1;
+
+    const ROW_DISTANCE: f64 = 1.0;
+
+    fn neighbor_offset(direction: usize, _y: usize) -> (isize, isize) {
+        [(1, 0), (0, -1), (-1, 0), (0, 1)][direction]
+    }
+
+    fn collide(raw: u8) -> u8 {
+        HPP_COLLISIONS[raw as usize]
+    }
+}
+
+/// Calculate one round of any lattice model on a grid that wraps around on all sides
+pub fn lattice_round<M: LatticeModel, const WIDTH: usize>(
+    grid: &[[Cell; WIDTH]],
+    result: &mut [[Cell; WIDTH]],
+) {
+    let height = grid.len();
+    assert!(
+        height % M::ROW_PERIOD == 0,
+        "The height of a periodic {} grid must be a multiple of {}",
+        M::NAME,
+        M::ROW_PERIOD
+    );
+
+    result.par_iter_mut().enumerate().for_each(|(y, row)| {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let mut raw = 0;
+            for direction in M::DIRECTIONS {
+                // Particles arrive from the neighbor in the opposite direction
+                let (dx, dy) = M::neighbor_offset(direction.opposite, y);
+                let source_x = (x as isize + dx).rem_euclid(WIDTH as isize) as usize;
+                let source_y = (y as isize + dy).rem_euclid(height as isize) as usize;
+                raw |= grid[source_y][source_x].raw & direction.bit;
+            }
+            cell.raw = M::collide(raw);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{cell::CollisionModel, periodic::periodic_round};
+
+    #[test]
+    fn fhp_moves_like_the_periodic_reference() {
+        const WIDTH: usize = 10;
+        // Lone particles never collide, so both rounds are deterministic
+        for (x, y) in [(0, 0), (4, 1), (9, 5)] {
+            for direction in Fhp::DIRECTIONS {
+                let mut grid = [[Cell::new(); WIDTH]; 6];
+                grid[y][x].raw = direction.bit;
+                let mut fhp = [[Cell::new(); WIDTH]; 6];
+                let mut reference = [[Cell::new(); WIDTH]; 6];
+                lattice_round::<Fhp, WIDTH>(&grid, &mut fhp);
+                periodic_round(&grid, &mut reference, CollisionModel::Real);
+                assert_eq!(fhp, reference);
+            }
+        }
+    }
+
+    fn rounds_keep_particles<M: LatticeModel>() {
+        const WIDTH: usize = 8;
+        let mut grid = [[Cell::new(); WIDTH]; 6];
+        let mut result = [[Cell::new(); WIDTH]; 6];
+        let all_directions = M::DIRECTIONS.iter().fold(0, |bits, d| bits | d.bit);
+        for (index, cell) in grid.iter_mut().flatten().enumerate() {
+            cell.raw = (index * 29 % 64) as u8 & all_directions;
+        }
+        let count = |grid: &[[Cell; WIDTH]]| -> u32 {
+            grid.iter().flatten().map(|c| c.raw.count_ones()).sum()
+        };
+        let particles = count(&grid);
+        for _ in 0..10 {
+            lattice_round::<M, WIDTH>(&grid, &mut result);
+            std::mem::swap(&mut grid, &mut result);
+        }
+        assert_eq!(count(&grid), particles);
+    }
+
+    #[test]
+    fn all_models_keep_particles() {
+        rounds_keep_particles::<Fhp>();
+        rounds_keep_particles::<Hpp>();
+    }
+
+    #[test]
+    fn hpp_keeps_momentum_of_every_row() {
+        // The reason why shear waves along the axes never decay in HPP
+        const WIDTH: usize = 10;
+        let mut grid = [[Cell::new(); WIDTH]; 10];
+        let mut result = [[Cell::new(); WIDTH]; 10];
+        for (index, cell) in grid.iter_mut().flatten().enumerate() {
+            cell.raw = (index * 7 % 16) as u8;
+        }
+        let row_momentum = |grid: &[[Cell; WIDTH]]| -> Vec<i32> {
+            grid.iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|c| (c.raw & HPP_EAST != 0) as i32 - (c.raw & HPP_WEST != 0) as i32)
+                        .sum()
+                })
+                .collect()
+        };
+        let momentum = row_momentum(&grid);
+        for _ in 0..10 {
+            lattice_round::<Hpp, WIDTH>(&grid, &mut result);
+            std::mem::swap(&mut grid, &mut result);
+        }
+        assert_eq!(row_momentum(&grid), momentum);
+    }
+}
@@ -0,0 +1,70 @@
+//! Allocation of large grids.
+//!
+//! On NUMA machines a page ends up on the node of the thread that touches it first.
+//! The rows are zeroed in parallel with the same rayon partitioning the stepping uses,
+//! so the threads later mostly calculate rows in their local memory.
+use rayon::prelude::*;
+
+use super::Cell;
+
+/// Allocate `height` empty rows, first touched by the rayon threads that will calculate them.
+///
+/// With `huge_pages` the kernel is asked to back the rows with transparent huge pages.
+pub fn allocate_rows<const WIDTH: usize>(height: usize, huge_pages: bool) -> Vec<[Cell; WIDTH]> {
+    let mut rows: Vec<[Cell; WIDTH]> = Vec::with_capacity(height);
+    if huge_pages {
+        advise_huge_pages(rows.spare_capacity_mut());
+    }
+    rows.spare_capacity_mut().par_iter_mut().for_each(|row| {
+        // A cell is a single byte and zero is an empty cell
+        unsafe { row.as_mut_ptr().write_bytes(0, 1) };
+    });
+    // Every row was initialized above
+    unsafe { rows.set_len(height) };
+    rows
+}
+
+/// Ask for transparent huge pages for all whole pages of `memory`. Only a hint, failures are reported and ignored
+#[cfg(target_os = "linux")]
+fn advise_huge_pages<T>(memory: &mut [T]) {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = memory.as_mut_ptr() as usize;
+    let end = start + std::mem::size_of_val(memory);
+    let aligned_start = start.next_multiple_of(page_size);
+    let aligned_end = end / page_size * page_size;
+    if aligned_end <= aligned_start {
+        return;
+    }
+    let result = unsafe {
+        libc::madvise(
+            aligned_start as *mut libc::c_void,
+            aligned_end - aligned_start,
+            libc::MADV_HUGEPAGE,
+        )
+    };
+    if result != 0 {
+        eprintln!(
+            "Transparent huge pages are not available: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_huge_pages<T>(_memory: &mut [T]) {
+    eprintln!("Transparent huge pages are only supported on Linux");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_empty() {
+        for huge_pages in [false, true] {
+            let rows = allocate_rows::<100>(5000, huge_pages);
+            assert_eq!(rows.len(), 5000);
+            assert!(rows.iter().flatten().all(|cell| cell.raw == 0));
+        }
+    }
+}
@@ -6,9 +6,11 @@ use super::{
 /// Calculate the movements for the core of a section
 /// Higly optimized, but not very readable.
 /// This should work really well with autovectorization, best use a CPU with AVX512
+///
+/// Without `COLLIDE` the particles only move. That is deterministic, so a second bit plane moves exactly like the cells
 #[inline(never)]
 // tag::movement_core_function[]
-pub fn movement_core<const WIDTH: usize>(
+pub fn movement_core<const WIDTH: usize, const COLLIDE: bool>(
     above: &[Cell; WIDTH - 1],
     current: &[Cell; WIDTH],
     below: &[Cell; WIDTH - 1],
@@ -35,7 +37,9 @@ pub fn movement_core<const WIDTH: usize>(
             // end::movement_core[]
 
             result.raw = new_cell;
-            if cfg!(use_real_collisions_in_core) {
+            if !COLLIDE {
+                // Only the movement
+            } else if cfg!(use_real_collisions_in_core) {
                 result.process_collision();
             } else {
                 // tag::collision_fake[]
@@ -94,7 +98,7 @@ pub fn movement_core<const WIDTH: usize>(
 // end::movement_core_function[]
 
 /// Calculate the movement of the core of the top row
-fn movement_core_top<const WIDTH: usize>(
+fn movement_core_top<const WIDTH: usize, const COLLIDE: bool>(
     current: &[Cell; WIDTH],
     below: &[Cell; WIDTH - 1],
     result: &mut [Cell; WIDTH - 2],
@@ -112,13 +116,15 @@ fn movement_core_top<const WIDTH: usize>(
                 | (east.raw & TO_WEST)
                 | (south_east.raw & TO_NORTH_WEST)
                 | (south_west.raw & TO_NORTH_EAST);
-            result.process_collision();
+            if COLLIDE {
+                result.process_collision();
+            }
         },
     )
 }
 
 /// Calculate the movement of the core of the bottom row
-fn movement_core_bottom<const WIDTH: usize>(
+fn movement_core_bottom<const WIDTH: usize, const COLLIDE: bool>(
     above: &[Cell; WIDTH - 1],
     current: &[Cell; WIDTH],
     result: &mut [Cell; WIDTH - 2],
@@ -136,12 +142,14 @@ fn movement_core_bottom<const WIDTH: usize>(
                 | (east.raw & TO_WEST)
                 | ((current.raw & TO_SOUTH_EAST) >> 2)
                 | ((current.raw & TO_SOUTH_WEST) >> 4);
-            result.process_collision();
+            if COLLIDE {
+                result.process_collision();
+            }
         },
     )
 }
 
-pub fn movement_even_row<const WIDTH: usize>(
+pub fn movement_even_row<const WIDTH: usize, const COLLIDE: bool>(
     above: &[Cell; WIDTH],
     current: &[Cell; WIDTH],
     below: &[Cell; WIDTH],
@@ -157,10 +165,12 @@ pub fn movement_even_row<const WIDTH: usize>(
         | (below[1].raw & TO_NORTH_WEST)
         | (current[1].raw & TO_WEST)
         | ((current[0].raw & TO_WEST) << 3);
-    result[0].process_collision();
+    if COLLIDE {
+        result[0].process_collision();
+    }
 
     // Handle core
-    movement_core(
+    movement_core::<WIDTH, COLLIDE>(
         above.rsplit_array_ref::<{ WIDTH - 1 }>().1,
         current,
         below.rsplit_array_ref::<{ WIDTH - 1 }>().1,
@@ -178,11 +188,13 @@ pub fn movement_even_row<const WIDTH: usize>(
         | ((current[WIDTH - 1].raw & TO_EAST) >> 3)
         | ((current[WIDTH - 1].raw & TO_NORTH_EAST) >> 1)
         | ((current[WIDTH - 1].raw & TO_SOUTH_EAST) << 1);
-    result[WIDTH - 1].process_collision();
+    if COLLIDE {
+        result[WIDTH - 1].process_collision();
+    }
 }
 
 /// Top row is always even
-pub fn movement_top_row<const WIDTH: usize>(
+pub fn movement_top_row<const WIDTH: usize, const COLLIDE: bool>(
     current: &[Cell; WIDTH],
     below: &[Cell; WIDTH],
     result: &mut [Cell; WIDTH],
@@ -199,10 +211,12 @@ pub fn movement_top_row<const WIDTH: usize>(
         | ((current[0].raw & TO_NORTH_WEST) << 4)
         | ((current[0].raw & TO_WEST) << 3);
     // end::top_right_movement_implementation[]
-    result[0].process_collision();
+    if COLLIDE {
+        result[0].process_collision();
+    }
 
     // Handle core
-    movement_core_top(
+    movement_core_top::<WIDTH, COLLIDE>(
         current,
         below.rsplit_array_ref::<{ WIDTH - 1 }>().1,
         result
@@ -219,10 +233,12 @@ pub fn movement_top_row<const WIDTH: usize>(
         | ((current[WIDTH - 1].raw & TO_EAST) >> 3)
         | ((current[WIDTH - 1].raw & TO_NORTH_EAST) << 3)
         | ((current[WIDTH - 1].raw & TO_SOUTH_EAST) >> 3);
-    result[WIDTH - 1].process_collision();
+    if COLLIDE {
+        result[WIDTH - 1].process_collision();
+    }
 }
 
-pub fn movement_odd_row<const WIDTH: usize>(
+pub fn movement_odd_row<const WIDTH: usize, const COLLIDE: bool>(
     above: &[Cell; WIDTH],
     current: &[Cell; WIDTH],
     below: &[Cell; WIDTH],
@@ -238,10 +254,12 @@ pub fn movement_odd_row<const WIDTH: usize>(
         | ((current[0].raw & TO_WEST) << 3)
         | ((current[0].raw & TO_NORTH_WEST) << 1)
         | ((current[0].raw & TO_SOUTH_WEST) >> 1);
-    result[0].process_collision();
+    if COLLIDE {
+        result[0].process_collision();
+    }
 
     // Handle core
-    movement_core(
+    movement_core::<WIDTH, COLLIDE>(
         above.split_array_ref::<{ WIDTH - 1 }>().0,
         current,
         below.split_array_ref::<{ WIDTH - 1 }>().0,
@@ -259,10 +277,12 @@ pub fn movement_odd_row<const WIDTH: usize>(
         | (below[WIDTH - 1].raw & TO_NORTH_WEST)
         | (current[WIDTH - 2].raw & TO_EAST)
         | ((current[WIDTH - 1].raw & TO_EAST) >> 3);
-    result[WIDTH - 1].process_collision();
+    if COLLIDE {
+        result[WIDTH - 1].process_collision();
+    }
 }
 
-pub fn movement_bottom_row<const WIDTH: usize>(
+pub fn movement_bottom_row<const WIDTH: usize, const COLLIDE: bool>(
     above: &[Cell; WIDTH],
     current: &[Cell; WIDTH],
     result: &mut [Cell; WIDTH],
@@ -277,10 +297,12 @@ pub fn movement_bottom_row<const WIDTH: usize>(
         | ((current[0].raw & TO_WEST) << 3)
         | ((current[0].raw & TO_NORTH_WEST) << 3)
         | ((current[0].raw & TO_SOUTH_WEST) >> 3);
-    result[0].process_collision();
+    if COLLIDE {
+        result[0].process_collision();
+    }
 
     // Handle core
-    movement_core_bottom(
+    movement_core_bottom::<WIDTH, COLLIDE>(
         above.split_array_ref::<{ WIDTH - 1 }>().0,
         current,
         result
@@ -297,32 +319,58 @@ pub fn movement_bottom_row<const WIDTH: usize>(
         | ((current[WIDTH - 1].raw & TO_EAST) >> 3)
         | ((current[WIDTH - 1].raw & TO_SOUTH_EAST) >> 2)
         | ((current[WIDTH - 1].raw & TO_SOUTH_WEST) >> 4);
-    result[WIDTH - 1].process_collision();
+    if COLLIDE {
+        result[WIDTH - 1].process_collision();
+    }
+}
+
+/// Bottom row of a grid with an odd number of rows, so the last row is even
+pub fn movement_bottom_even_row<const WIDTH: usize, const COLLIDE: bool>(
+    above: &[Cell; WIDTH],
+    current: &[Cell; WIDTH],
+    result: &mut [Cell; WIDTH],
+) where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    // Handle border of first cell
+    result[0].raw = (above[0].raw & TO_SOUTH_EAST)
+        | (above[1].raw & TO_SOUTH_WEST)
+        | (current[1].raw & TO_WEST)
+        | ((current[0].raw & TO_WEST) << 3)
+        | ((current[0].raw & TO_SOUTH_EAST) >> 2)
+        | ((current[0].raw & TO_SOUTH_WEST) >> 4);
+    if COLLIDE {
+        result[0].process_collision();
+    }
+
+    // Handle core
+    movement_core_bottom::<WIDTH, COLLIDE>(
+        above.rsplit_array_ref::<{ WIDTH - 1 }>().1,
+        current,
+        result
+            .rsplit_array_mut::<{ WIDTH - 1 }>()
+            .1
+            .split_array_mut::<{ WIDTH - 2 }>()
+            .0,
+    );
+
+    // Handle border of last cell
+    result[WIDTH - 1].raw = (above[WIDTH - 1].raw & TO_SOUTH_EAST)
+        | (current[WIDTH - 2].raw & TO_EAST)
+        | ((current[WIDTH - 1].raw & TO_EAST) >> 3)
+        | ((current[WIDTH - 1].raw & TO_NORTH_EAST) << 3)
+        | ((current[WIDTH - 1].raw & TO_SOUTH_EAST) >> 3)
+        | ((current[WIDTH - 1].raw & TO_SOUTH_WEST) >> 3);
+    if COLLIDE {
+        result[WIDTH - 1].process_collision();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    pub fn print_section<const WIDTH: usize>(section: &[[Cell; WIDTH]])
-    where
-        [(); WIDTH]:,
-    {
-        for (index, row) in section.iter().enumerate() {
-            if (index % 2) == 0 {
-                for cell in row.iter() {
-                    eprint!(" ");
-                    eprint!("{:?}", cell.get_particles());
-                }
-            } else {
-                for cell in row.iter() {
-                    eprint!("{:?}", cell.get_particles());
-                    eprint!(" ");
-                }
-            }
-            eprintln!();
-        }
-    }
+    use crate::lgca::terminal::{render_frame, Glyphs, Viewport};
 
     #[test]
     fn even_rows_on_east_and_west() {
@@ -336,13 +384,13 @@ mod tests {
         current[WIDTH - 1].set_to_east(true);
         current[0].set_to_west(true);
 
-        movement_even_row(&above, &current, &below, &mut section);
+        movement_even_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(section[0].to_east(), true);
         assert_eq!(section[WIDTH - 1].to_west(), true);
 
         std::mem::swap(&mut current, &mut section);
-        movement_even_row(&above, &current, &below, &mut section);
+        movement_even_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(section[1].to_east(), true);
         assert_eq!(section[WIDTH - 2].to_west(), true);
@@ -360,7 +408,7 @@ mod tests {
         above[1].set_to_south_east(true);
         above[1].set_to_south_west(true);
 
-        movement_even_row(&above, &current, &below, &mut section);
+        movement_even_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(section[0].to_south_west(), true);
         assert_eq!(section[1].to_south_east(), true);
@@ -384,7 +432,7 @@ mod tests {
         current[0].set_to_north_west(true);
         current[1].set_to_north_east(true);
 
-        movement_top_row(&current, &below, &mut section);
+        movement_top_row::<WIDTH, true>(&current, &below, &mut section);
 
         assert_eq!(
             section
@@ -410,7 +458,7 @@ mod tests {
 
         current[0].set_to_south_west(true);
 
-        movement_odd_row(&above, &current, &below, &mut section);
+        movement_odd_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(
             section
@@ -434,7 +482,7 @@ mod tests {
 
         current[0].set_to_north_west(true);
 
-        movement_odd_row(&above, &current, &below, &mut section);
+        movement_odd_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(
             section
@@ -458,7 +506,7 @@ mod tests {
 
         current[WIDTH - 1].set_to_south_east(true);
 
-        movement_even_row(&above, &current, &below, &mut section);
+        movement_even_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(
             section
@@ -482,7 +530,7 @@ mod tests {
 
         current[WIDTH - 1].set_to_north_east(true);
 
-        movement_even_row(&above, &current, &below, &mut section);
+        movement_even_row::<WIDTH, true>(&above, &current, &below, &mut section);
 
         assert_eq!(
             section
@@ -495,6 +543,47 @@ mod tests {
         assert_eq!(section[WIDTH - 1].to_north_west(), true);
     }
 
+    #[test]
+    #[cfg(not(use_real_collisions_in_core))]
+    fn single_cell_fake_collisions_match_movement_core() {
+        // The fake collisions only look at the new cell and one bit of three neighbors
+        for new_cell in 0..64u8 {
+            for neighbor_bits in 0..8u8 {
+                let mut above = [Cell::new(); 2];
+                let mut current = [Cell::new(); 3];
+                let mut below = [Cell::new(); 2];
+                let mut result = [Cell::new(); 1];
+
+                current[0].raw = new_cell & TO_EAST;
+                above[0].raw = new_cell & TO_SOUTH_EAST;
+                above[1].raw = new_cell & TO_SOUTH_WEST;
+                current[2].raw = new_cell & TO_WEST;
+                below[1].raw = new_cell & TO_NORTH_WEST;
+                below[0].raw = new_cell & TO_NORTH_EAST;
+
+                if neighbor_bits & 0b001 != 0 {
+                    below[1].raw |= TO_SOUTH_EAST;
+                }
+                if neighbor_bits & 0b010 != 0 {
+                    current[2].raw |= TO_EAST;
+                }
+                if neighbor_bits & 0b100 != 0 {
+                    above[1].raw |= TO_NORTH_EAST;
+                }
+
+                movement_core::<3, true>(&above, &current, &below, &mut result);
+
+                let mut cell = Cell { raw: new_cell };
+                cell.process_fake_collision(&below[1], &current[2], &above[1]);
+                assert_eq!(
+                    cell.raw, result[0].raw,
+                    "{:#08b} with neighbors {:#03b}",
+                    new_cell, neighbor_bits
+                );
+            }
+        }
+    }
+
     #[test]
     fn interactive_test() {
         const WIDTH: usize = 30;
@@ -503,24 +592,32 @@ mod tests {
         let mut sections = [[Cell::new(); WIDTH]; HEIGHT];
         let mut sections_b = [[Cell::new(); WIDTH]; HEIGHT];
 
+        let viewport = Viewport {
+            x: 0,
+            y: 0,
+            zoom: 1,
+            columns: WIDTH,
+            lines: HEIGHT.div_ceil(2),
+        };
+
         sections[1][1].raw = 0b00111111;
         // sections[0][1].raw = TO_NORTH_WEST;
         eprintln!("============================ Intial");
-        print_section(&sections[..]);
+        eprint!("{}", render_frame(&sections, &viewport, Glyphs::HalfBlocks));
         for round in 0..50 {
-            movement_top_row(&sections[0], &sections[1], &mut sections_b[0]);
+            movement_top_row::<WIDTH, true>(&sections[0], &sections[1], &mut sections_b[0]);
             for (row, ([above, current, below], result)) in sections
                 .array_windows::<3>()
                 .zip(sections_b.iter_mut().skip(1))
                 .enumerate()
             {
                 if ((row + 1) % 2) == 0 {
-                    movement_even_row(above, current, below, result);
+                    movement_even_row::<WIDTH, true>(above, current, below, result);
                 } else {
-                    movement_odd_row(above, current, below, result);
+                    movement_odd_row::<WIDTH, true>(above, current, below, result);
                 }
             }
-            movement_bottom_row(
+            movement_bottom_row::<WIDTH, true>(
                 &sections[WIDTH - 2],
                 &sections[WIDTH - 1],
                 &mut sections_b[WIDTH - 1],
@@ -531,7 +628,10 @@ mod tests {
             }
 
             eprintln!("============================ Round {}", round);
-            print_section(&sections_b[..]);
+            eprint!(
+                "{}",
+                render_frame(&sections_b, &viewport, Glyphs::HalfBlocks)
+            );
             std::mem::swap(&mut sections, &mut sections_b);
         }
         // assert_eq!(section[0].to_east(), true);
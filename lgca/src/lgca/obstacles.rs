@@ -0,0 +1,330 @@
+//! Solid obstacles in the flow and the forces the particles exert on them.
+//!
+//! Every shape is a solid body. Particles that arrive in a solid cell do not collide, they bounce back where they came
+//! from. A particle with velocity `c` that bounces back hands the momentum `2 c` to the body, the sum over all solid
+//! cells of a body in a round is the force on it. The drag is the force to the east, the lift the force to the north.
+use std::{io::Write, ops::Range, path::PathBuf, str::FromStr};
+
+use super::{
+    lattice::{Fhp, LatticeModel, FHP_EVEN_ROW_OFFSETS, FHP_ODD_ROW_OFFSETS},
+    output::{Output, RootCsv},
+    simulation::Simulation,
+    Cell,
+};
+
+/// A solid body. Coordinates are global cells, lengths are cell widths
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// A cylinder around the center of cell (`x`, `y`). The center is shifted like the cells of the row nearest to `y`
+    Circle { x: f64, y: f64, radius: f64 },
+    /// The cells `x..x + width` of the rows `y..y + height`
+    Rectangle {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    /// Parse `circle:x,y,radius` or `rectangle:x,y,width,height`
+    fn from_str(description: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid obstacle {}, expected circle:x,y,radius or rectangle:x,y,width,height",
+                description
+            )
+        };
+        let (kind, numbers) = description.split_once(':').ok_or_else(invalid)?;
+        let numbers = numbers
+            .split(',')
+            .map(|number| number.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match (kind, &numbers[..]) {
+            ("circle", [x, y, radius]) if *radius > 0.0 => Ok(Shape::Circle {
+                x: *x,
+                y: *y,
+                radius: *radius,
+            }),
+            ("rectangle", [x, y, width, height])
+                if numbers
+                    .iter()
+                    .all(|number| *number >= 0.0 && number.fract() == 0.0) =>
+            {
+                Ok(Shape::Rectangle {
+                    x: *x as usize,
+                    y: *y as usize,
+                    width: *width as usize,
+                    height: *height as usize,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Shape {
+    /// Whether cell (`x`, `y`) is solid. Circles are round in the plane, not in cell coordinates
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        match *self {
+            Shape::Circle {
+                x: center_x,
+                y: center_y,
+                radius,
+            } => {
+                let (x, y) = Fhp::position(x, y);
+                let shift = if (center_y.round() as usize) % 2 == 0 {
+                    0.5
+                } else {
+                    0.0
+                };
+                let center = (center_x + shift, center_y * 3f64.sqrt() / 2.0);
+                (x - center.0).powi(2) + (y - center.1).powi(2) <= radius.powi(2)
+            }
+            Shape::Rectangle {
+                x: left,
+                y: top,
+                width,
+                height,
+            } => (left..left + width).contains(&x) && (top..top + height).contains(&y),
+        }
+    }
+}
+
+/// The solid cells in the rows of one rank.
+///
+/// Obstacles should not touch the edges of the grid, particles that would arrive from beyond an edge are ignored.
+pub struct Obstacles {
+    pub shapes: Vec<Shape>,
+    /// Solid cells of every row of this rank with the index of their shape. Overlapping shapes belong to the first
+    cells: Vec<Vec<(usize, usize)>>,
+    row_offset: usize,
+    /// Momentum the particles in the rows of this rank handed to every body in the last round
+    pub momentum_transfer: Vec<(f64, f64)>,
+}
+
+impl Obstacles {
+    pub fn new(shapes: Vec<Shape>, width: usize, rows: Range<usize>) -> Self {
+        let cells = rows
+            .clone()
+            .map(|y| {
+                (0..width)
+                    .filter_map(|x| {
+                        let body = shapes.iter().position(|shape| shape.contains(x, y))?;
+                        Some((x, body))
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            momentum_transfer: vec![(0.0, 0.0); shapes.len()],
+            shapes,
+            cells,
+            row_offset: rows.start,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// Number of solid cells in the rows of this rank
+    pub fn solid_cells(&self) -> usize {
+        self.cells.iter().map(Vec::len).sum()
+    }
+
+    /// Remove the particles inside the bodies
+    pub fn clear<const WIDTH: usize>(&self, grid: &mut [[Cell; WIDTH]]) {
+        for (row, cells) in grid.iter_mut().zip(&self.cells) {
+            for (x, _) in cells {
+                row[*x].raw = 0;
+            }
+        }
+    }
+
+    /// Replace the solid cells of the new round with the particles that arrived from the old round, reversed.
+    ///
+    /// The rows above and below the old grid are `None` at the top and bottom of the whole grid.
+    pub fn bounce_back<const WIDTH: usize>(
+        &mut self,
+        above: Option<&[Cell; WIDTH]>,
+        old: &[[Cell; WIDTH]],
+        below: Option<&[Cell; WIDTH]>,
+        new: &mut [[Cell; WIDTH]],
+    ) {
+        self.momentum_transfer.fill((0.0, 0.0));
+        for (y, cells) in self.cells.iter().enumerate() {
+            let even = (self.row_offset + y) % 2 == 0;
+            for (x, body) in cells {
+                let mut arrived = 0;
+                let offsets = if even {
+                    FHP_EVEN_ROW_OFFSETS
+                } else {
+                    FHP_ODD_ROW_OFFSETS
+                };
+                for (direction, (offset_x, offset_y)) in offsets.iter().enumerate() {
+                    let neighbor_x = *x as isize + offset_x;
+                    let row = match y as isize + offset_y {
+                        -1 => above,
+                        row if row as usize == old.len() => below,
+                        row => Some(&old[row as usize]),
+                    };
+                    if let (Some(row), 0..) = (row, neighbor_x) {
+                        if let Some(neighbor) = row.get(neighbor_x as usize) {
+                            // The neighbor sends the particles moving in the opposite direction
+                            let opposite = Fhp::DIRECTIONS[direction].opposite;
+                            arrived |= neighbor.raw & Fhp::DIRECTIONS[opposite].bit;
+                        }
+                    }
+                }
+                for direction in Fhp::DIRECTIONS {
+                    if arrived & direction.bit != 0 {
+                        let transfer = &mut self.momentum_transfer[*body];
+                        transfer.0 += 2.0 * direction.velocity.0;
+                        transfer.1 += 2.0 * direction.velocity.1;
+                    }
+                }
+                new[y][*x].raw = ((arrived << 3) | (arrived >> 3)) & 0b00111111;
+            }
+        }
+    }
+}
+
+/// Writes the drag and lift on every body of the whole grid after every round into `forces.csv`.
+///
+/// The momentum transfer of all ranks is summed up, only the first rank writes.
+pub struct ObstacleForces {
+    csv: RootCsv,
+    /// Sum of the drag and lift on every body over all observed rounds
+    sums: Vec<(f64, f64)>,
+    rounds: usize,
+}
+
+impl ObstacleForces {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            csv: RootCsv::new(path, "round,body,drag,lift".to_string()),
+            sums: Vec::new(),
+            rounds: 0,
+        }
+    }
+
+    /// Mean drag and lift on every body over all observed rounds
+    pub fn mean(&self) -> Vec<(f64, f64)> {
+        let rounds = self.rounds.max(1) as f64;
+        self.sums
+            .iter()
+            .map(|(drag, lift)| (drag / rounds, lift / rounds))
+            .collect()
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for ObstacleForces {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        // The initial state did not push anything yet
+        if simulation.round() == 0 {
+            return;
+        }
+        let local: Vec<f64> = simulation
+            .obstacles()
+            .momentum_transfer
+            .iter()
+            .flat_map(|(x, y)| [*x, *y])
+            .collect();
+        let total = simulation.sum_over_ranks(&local);
+        // y points south, lift points north
+        let forces: Vec<(f64, f64)> = total.chunks_exact(2).map(|f| (f[0], -f[1])).collect();
+        self.sums.resize(forces.len(), (0.0, 0.0));
+        for (sum, (drag, lift)) in self.sums.iter_mut().zip(&forces) {
+            sum.0 += drag;
+            sum.1 += lift;
+        }
+        self.rounds += 1;
+
+        let Some(file) = self.csv.file(simulation.rank()) else {
+            return;
+        };
+        for (body, (drag, lift)) in forces.iter().enumerate() {
+            writeln!(file, "{},{},{},{}", simulation.round(), body, drag, lift).unwrap();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.csv.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{
+        cell::{TO_EAST, TO_NORTH_EAST, TO_SOUTH_WEST, TO_WEST},
+        testing::TempDir,
+    };
+
+    #[test]
+    fn parse_shapes() {
+        assert_eq!(
+            "circle:20,10.5,4".parse(),
+            Ok(Shape::Circle {
+                x: 20.0,
+                y: 10.5,
+                radius: 4.0
+            })
+        );
+        assert_eq!(
+            "rectangle:1,2,3,4".parse(),
+            Ok(Shape::Rectangle {
+                x: 1,
+                y: 2,
+                width: 3,
+                height: 4
+            })
+        );
+        assert!("rectangle:1,2,3.5,4".parse::<Shape>().is_err());
+        assert!("circle:1,2".parse::<Shape>().is_err());
+        assert!("triangle:1,2,3".parse::<Shape>().is_err());
+
+        let circle: Shape = "circle:10,10,2".parse().unwrap();
+        assert!(circle.contains(10, 10) && circle.contains(12, 10) && circle.contains(9, 11));
+        assert!(!circle.contains(13, 10) && !circle.contains(10, 13));
+    }
+
+    #[test]
+    fn fractional_circle_centers_are_shifted_like_the_nearest_row() {
+        // Row 10 is the nearest one, so the center is shifted half a cell to the east like the even rows
+        let circle: Shape = "circle:10,9.6,0.6".parse().unwrap();
+        assert!(circle.contains(10, 10));
+        assert!(!circle.contains(9, 10) && !circle.contains(10, 9));
+    }
+
+    #[test]
+    fn particles_bounce_back_and_push_the_body() {
+        let mut simulation = Simulation::<12>::new(8, None);
+        simulation.grid_mut()[3][4].raw = TO_EAST;
+        simulation.grid_mut()[4][5].raw = TO_NORTH_EAST;
+        // Particles inside the body are removed
+        simulation.grid_mut()[3][6].raw = TO_WEST;
+        simulation.set_obstacles(vec!["rectangle:6,2,2,4".parse().unwrap()]);
+        assert_eq!(simulation.particles(), 2);
+
+        let directory = TempDir::new("forces");
+        let mut forces = ObstacleForces::new(directory.join("forces.csv"));
+        simulation.run(4, &mut [&mut forces]);
+        Output::<12>::finish(&mut forces);
+
+        assert_eq!(simulation.particles(), 2);
+        // The particle to the east hits the body in the second round and is back at its start after the fourth
+        assert_eq!(simulation.grid()[3][4].raw, TO_WEST);
+        assert_eq!(simulation.grid()[6][4].raw, TO_SOUTH_WEST);
+        let (drag, lift) = forces.mean()[0];
+        assert!((drag * 4.0 - 3.0).abs() < 1e-12);
+        assert!((lift * 4.0 - 3f64.sqrt()).abs() < 1e-12);
+
+        let lines = std::fs::read_to_string(&forces.csv.path).unwrap();
+        assert_eq!(lines.lines().count(), 5);
+    }
+}
@@ -0,0 +1,278 @@
+use ril::{Image, Rgb};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use super::{
+    simulation::Simulation,
+    visualization::{draw_cells_detailed, draw_species, save_webp},
+};
+
+/// Something that watches a running simulation, like a renderer or a statistics collector
+pub trait Output<const WIDTH: usize> {
+    /// Called with the state before the first round and after every round
+    fn observe(&mut self, simulation: &Simulation<WIDTH>);
+
+    /// Called once after the last round
+    fn finish(&mut self) {}
+}
+
+/// A CSV file that only the first rank writes. It is created with its header when the first line gets written
+pub struct RootCsv {
+    pub path: PathBuf,
+    header: String,
+    file: Option<BufWriter<File>>,
+}
+
+impl RootCsv {
+    pub fn new(path: PathBuf, header: String) -> Self {
+        Self {
+            path,
+            header,
+            file: None,
+        }
+    }
+
+    /// The file to write the lines of a round into on the first rank, `None` on all other ranks
+    pub fn file(&mut self, rank: i32) -> Option<&mut BufWriter<File>> {
+        if rank != 0 {
+            return None;
+        }
+        Some(self.file.get_or_insert_with(|| {
+            let mut file = BufWriter::new(File::create(&self.path).unwrap());
+            writeln!(file, "{}", self.header).unwrap();
+            file
+        }))
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(file) = self.file.as_mut() {
+            file.flush().unwrap();
+        }
+    }
+}
+
+/// Renders the rows of this rank into an animated WebP
+pub struct WebPRenderer {
+    filename: PathBuf,
+    scaling: f64,
+    time_per_round: Duration,
+    time_per_frame: Duration,
+    gif_time: Duration,
+    images: Vec<Image<Rgb>>,
+}
+
+impl WebPRenderer {
+    pub fn new(
+        filename: PathBuf,
+        rounds_per_second: usize,
+        frames_per_second: usize,
+        scaling: f64,
+    ) -> Self {
+        Self {
+            filename,
+            scaling,
+            time_per_round: Duration::from_secs_f64(1.0 / rounds_per_second as f64),
+            time_per_frame: Duration::from_secs_f64(1.0 / (frames_per_second as f64).max(1.0)),
+            gif_time: Duration::new(0, 0),
+            images: Vec::new(),
+        }
+    }
+
+    /// Number of frames rendered so far
+    pub fn frames(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Write all frames rendered so far to the file
+    pub fn save(&mut self) {
+        save_webp(
+            std::mem::take(&mut self.images),
+            self.time_per_frame,
+            self.filename.to_str().unwrap(),
+        );
+    }
+
+    fn render<const WIDTH: usize>(&mut self, simulation: &Simulation<WIDTH>) {
+        self.images.push(render_scaled(simulation, self.scaling));
+    }
+}
+
+/// Render the rows of this rank, in red and blue with two species, and scale the image by `scaling`
+pub fn render_scaled<const WIDTH: usize>(
+    simulation: &Simulation<WIDTH>,
+    scaling: f64,
+) -> Image<Rgb> {
+    let image = match simulation.colors() {
+        Some(colors) => draw_species(simulation.grid(), colors),
+        None => draw_cells_detailed(simulation.grid()),
+    };
+    image.resized(
+        (WIDTH as f64 * scaling) as u32,
+        (simulation.height() as f64 * scaling) as u32,
+        ril::ResizeAlgorithm::Lanczos3,
+    )
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for WebPRenderer {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        if simulation.round() == 0 {
+            self.render(simulation);
+            return;
+        }
+        self.gif_time += self.time_per_round;
+        while self.gif_time >= self.time_per_frame {
+            self.gif_time -= self.time_per_frame;
+            eprintln!("============================ Round {}", simulation.round());
+            self.render(simulation);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.save();
+    }
+}
+
+/// Writes the rows of this rank as uncompressed YUV4MPEG2 video, which most video tools can read directly.
+///
+/// A filename of `-` streams the video to stdout, so it can be piped into an encoder or a player.
+///
+/// The frame rate goes into the header, so there are no per frame delays that could drift.
+/// Frame `n` shows the first round at or after `n / frames_per_second` seconds, counted from the first observed round.
+pub struct Y4mRenderer {
+    filename: PathBuf,
+    scaling: f64,
+    rounds_per_second: usize,
+    frames_per_second: usize,
+    frames: usize,
+    /// Round of the first observed state, which is not zero after restoring a snapshot
+    first_round: Option<usize>,
+    file: Option<BufWriter<Box<dyn Write>>>,
+}
+
+impl Y4mRenderer {
+    pub fn new(
+        filename: PathBuf,
+        rounds_per_second: usize,
+        frames_per_second: usize,
+        scaling: f64,
+    ) -> Self {
+        assert!(
+            rounds_per_second > 0 && frames_per_second > 0,
+            "Videos need a positive speed and framerate"
+        );
+        Self {
+            filename,
+            scaling,
+            rounds_per_second,
+            frames_per_second,
+            frames: 0,
+            first_round: None,
+            file: None,
+        }
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    fn write_frame(&mut self, image: &Image<Rgb>) {
+        let (width, height) = (image.width(), image.height());
+        let file = self.file.get_or_insert_with(|| {
+            let output: Box<dyn Write> = if self.filename.as_os_str() == "-" {
+                Box::new(std::io::stdout())
+            } else {
+                Box::new(File::create(&self.filename).unwrap())
+            };
+            let mut file = BufWriter::new(output);
+            writeln!(
+                file,
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                width, height, self.frames_per_second
+            )
+            .unwrap();
+            file
+        });
+
+        // Full resolution planes of BT.601 limited range
+        let mut planes: [Vec<u8>; 3] =
+            std::array::from_fn(|_| Vec::with_capacity(image.data.len()));
+        for pixel in &image.data {
+            let (r, g, b) = (pixel.r as f64, pixel.g as f64, pixel.b as f64);
+            let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+            let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+            let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+            planes[0].push(y.round() as u8);
+            planes[1].push(u.round() as u8);
+            planes[2].push(v.round() as u8);
+        }
+        file.write_all(b"FRAME\n").unwrap();
+        for plane in planes {
+            file.write_all(&plane).unwrap();
+        }
+        self.frames += 1;
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for Y4mRenderer {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        // Frame n is due once round / rounds_per_second >= n / frames_per_second
+        let round = simulation.round() - *self.first_round.get_or_insert(simulation.round());
+        let mut image = None;
+        while round * self.frames_per_second >= self.frames * self.rounds_per_second {
+            let image = image.get_or_insert_with(|| render_scaled(simulation, self.scaling));
+            self.write_frame(image);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(file) = self.file.as_mut() {
+            file.flush().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::testing::TempDir;
+
+    #[test]
+    fn y4m_frames_follow_the_framerate() {
+        let directory = TempDir::new("output");
+        let filename = directory.join("output.y4m");
+        let mut simulation = Simulation::<10>::new(6, None);
+        simulation.add_seeded_noise(0.3, 0);
+        let mut renderer = Y4mRenderer::new(filename.clone(), 40, 30, 1.0);
+        simulation.run(20, &mut [&mut renderer]);
+        Output::<10>::finish(&mut renderer);
+
+        // 20 rounds at 40 rounds per second are half a second, so 15 frames after the first one
+        assert_eq!(renderer.frames(), 16);
+        let video = std::fs::read(&filename).unwrap();
+        let header = b"YUV4MPEG2 W10 H6 F30:1 Ip A1:1 C444\n";
+        assert!(video.starts_with(header));
+        assert_eq!(
+            video.len(),
+            header.len() + 16 * (b"FRAME\n".len() + 3 * 10 * 6)
+        );
+    }
+
+    #[test]
+    fn y4m_frames_start_at_the_restored_round() {
+        let directory = TempDir::new("output_restored");
+        let filename = directory.join("output.y4m");
+        let mut simulation = Simulation::<10>::new(6, None);
+        simulation.set_round(1000);
+        let mut renderer = Y4mRenderer::new(filename.clone(), 40, 30, 1.0);
+        simulation.observe(&mut [&mut renderer]);
+        assert_eq!(renderer.frames(), 1);
+        simulation.run(20, &mut [&mut renderer]);
+        Output::<10>::finish(&mut renderer);
+        assert_eq!(renderer.frames(), 16);
+    }
+}
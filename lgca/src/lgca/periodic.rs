@@ -0,0 +1,132 @@
+use rayon::prelude::*;
+
+use super::{
+    cell::{
+        CollisionModel, TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST,
+        TO_WEST,
+    },
+    lattice::{Fhp, LatticeModel},
+    Cell,
+};
+
+/// Positions of the neighbors of a cell on a grid that wraps around on all sides.
+///
+/// Even rows are shifted half a cell to the east, like in `new_movements`.
+/// The height has to be even, otherwise the row parity breaks at the wrap.
+pub struct Neighbors {
+    pub west: (usize, usize),
+    pub north_west: (usize, usize),
+    pub north_east: (usize, usize),
+    pub east: (usize, usize),
+    pub south_east: (usize, usize),
+    pub south_west: (usize, usize),
+}
+
+impl Neighbors {
+    pub fn of(x: usize, y: usize, width: usize, height: usize) -> Self {
+        let neighbor = |direction: usize| {
+            let (dx, dy) = Fhp::neighbor_offset(direction, y);
+            (
+                (x as isize + dx).rem_euclid(width as isize) as usize,
+                (y as isize + dy).rem_euclid(height as isize) as usize,
+            )
+        };
+        // In the order of `Fhp::DIRECTIONS`
+        Self {
+            east: neighbor(0),
+            north_east: neighbor(1),
+            north_west: neighbor(2),
+            west: neighbor(3),
+            south_west: neighbor(4),
+            south_east: neighbor(5),
+        }
+    }
+}
+
+/// Move the particles of the neighbors into a cell.
+///
+/// `raw_at` returns the bits of the cell at a position, so this also works for other bit planes.
+pub fn propagate(neighbors: &Neighbors, raw_at: impl Fn((usize, usize)) -> u8) -> u8 {
+    (raw_at(neighbors.west) & TO_EAST)
+        | (raw_at(neighbors.north_west) & TO_SOUTH_EAST)
+        | (raw_at(neighbors.north_east) & TO_SOUTH_WEST)
+        | (raw_at(neighbors.east) & TO_WEST)
+        | (raw_at(neighbors.south_east) & TO_NORTH_WEST)
+        | (raw_at(neighbors.south_west) & TO_NORTH_EAST)
+}
+
+/// Calculate one round on a grid that wraps around on all sides.
+///
+/// This is not optimized at all. It is meant as a reference for experiments and tests.
+pub fn periodic_round<const WIDTH: usize>(
+    grid: &[[Cell; WIDTH]],
+    result: &mut [[Cell; WIDTH]],
+    collision_model: CollisionModel,
+) {
+    let height = grid.len();
+    assert!(height % 2 == 0, "Periodic grids need an even height");
+
+    result.par_iter_mut().enumerate().for_each(|(y, row)| {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let neighbors = Neighbors::of(x, y, WIDTH, height);
+            let at = |(x, y): (usize, usize)| &grid[y][x];
+
+            cell.raw = propagate(&neighbors, |position| at(position).raw);
+
+            match collision_model {
+                CollisionModel::Real => cell.process_collision(),
+                CollisionModel::Fake => cell.process_fake_collision(
+                    at(neighbors.south_east),
+                    at(neighbors.east),
+                    at(neighbors.north_east),
+                ),
+                CollisionModel::Chiral { clockwise } => cell.process_chiral_collision(clockwise),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_particles<const WIDTH: usize>(grid: &[[Cell; WIDTH]]) -> usize {
+        grid.iter()
+            .flatten()
+            .map(|cell| cell.get_particles() as usize)
+            .sum()
+    }
+
+    #[test]
+    fn particles_wrap_around_all_borders() {
+        const WIDTH: usize = 6;
+        let mut grid = [[Cell::new(); WIDTH]; 4];
+        let mut result = [[Cell::new(); WIDTH]; 4];
+
+        grid[0][0].raw = TO_WEST | TO_NORTH_WEST | TO_NORTH_EAST;
+        periodic_round(&grid, &mut result, CollisionModel::Fake);
+
+        assert_eq!(result[0][WIDTH - 1].raw, TO_WEST);
+        assert_eq!(result[3][0].raw, TO_NORTH_WEST);
+        assert_eq!(result[3][1].raw, TO_NORTH_EAST);
+    }
+
+    #[test]
+    fn particles_are_conserved() {
+        const WIDTH: usize = 12;
+        let mut grid = [[Cell::new(); WIDTH]; 8];
+        let mut result = [[Cell::new(); WIDTH]; 8];
+        for (index, cell) in grid.iter_mut().flatten().enumerate() {
+            cell.raw = (index * 37 % 64) as u8;
+        }
+        let particles = count_particles(&grid);
+
+        for collision_model in CollisionModel::ALL {
+            for _ in 0..20 {
+                periodic_round(&grid, &mut result, collision_model);
+                std::mem::swap(&mut grid, &mut result);
+            }
+            assert_eq!(count_particles(&grid), particles);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Random porous media made of discs.
+use rand::prelude::*;
+
+use super::{
+    lattice::{Fhp, LatticeModel},
+    obstacles::Shape,
+};
+
+/// Random grains that do not overlap, placed until a target porosity is reached
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PorousMedium {
+    /// Fraction of the cells that stay fluid
+    pub porosity: f64,
+    /// Radius of every grain in cell widths
+    pub radius: f64,
+    pub seed: u64,
+}
+
+/// Free space between two grains, so that the pores stay connected
+const GAP: f64 = 1.5;
+
+/// Give up after this many grains in a row did not fit
+const ATTEMPTS: usize = 10000;
+
+impl PorousMedium {
+    /// Place grains at random positions in a grid of `width` x `height` cells.
+    ///
+    /// A grain that would overlap another one or touch the edge of the grid is skipped. Placing stops as soon as
+    /// the porosity is at or below the target, so it ends up slightly below it.
+    pub fn generate(&self, width: usize, height: usize) -> Vec<Shape> {
+        assert!(
+            self.porosity > 0.0 && self.porosity < 1.0,
+            "The porosity must be between 0 and 1, not {}",
+            self.porosity
+        );
+        let random = &mut SmallRng::seed_from_u64(self.seed);
+        let margin = self.radius.ceil() as usize + 1;
+        assert!(
+            width > 2 * margin && height > 2 * margin,
+            "Grains with radius {} do not fit into the grid",
+            self.radius
+        );
+
+        let mut solid = vec![false; width * height];
+        let mut solid_cells = 0;
+        let mut centers: Vec<(f64, f64)> = Vec::new();
+        let mut grains = Vec::new();
+        let mut failures = 0;
+        while 1.0 - solid_cells as f64 / solid.len() as f64 > self.porosity {
+            assert!(
+                failures < ATTEMPTS,
+                "Could not reach a porosity of {} with grains of radius {}, stuck at {}",
+                self.porosity,
+                self.radius,
+                1.0 - solid_cells as f64 / solid.len() as f64
+            );
+            let (x, y) = (
+                random.gen_range(margin..width - margin),
+                random.gen_range(margin..height - margin),
+            );
+            let center = Fhp::position(x, y);
+            let overlaps = centers.iter().any(|other| {
+                (center.0 - other.0).powi(2) + (center.1 - other.1).powi(2)
+                    < (2.0 * self.radius + GAP).powi(2)
+            });
+            if overlaps {
+                failures += 1;
+                continue;
+            }
+            failures = 0;
+
+            let grain = Shape::Circle {
+                x: x as f64,
+                y: y as f64,
+                radius: self.radius,
+            };
+            for grain_y in y - margin..=y + margin {
+                for grain_x in x - margin..=x + margin {
+                    let index = grain_y * width + grain_x;
+                    if !solid[index] && grain.contains(grain_x, grain_y) {
+                        solid[index] = true;
+                        solid_cells += 1;
+                    }
+                }
+            }
+            centers.push(center);
+            grains.push(grain);
+        }
+        grains
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grains_reach_the_porosity_without_overlapping() {
+        let medium = PorousMedium {
+            porosity: 0.7,
+            radius: 3.0,
+            seed: 5,
+        };
+        let grains = medium.generate(80, 60);
+        assert_eq!(grains, medium.generate(80, 60));
+
+        let solid = (0..60)
+            .flat_map(|y| (0..80).map(move |x| (x, y)))
+            .filter(|(x, y)| grains.iter().any(|grain| grain.contains(*x, *y)))
+            .count();
+        let porosity = 1.0 - solid as f64 / (80 * 60) as f64;
+        assert!(porosity <= 0.7 && porosity > 0.65, "{}", porosity);
+
+        for (index, grain) in grains.iter().enumerate() {
+            for other in &grains[index + 1..] {
+                let overlap = (0..60)
+                    .flat_map(|y| (0..80).map(move |x| (x, y)))
+                    .any(|(x, y)| grain.contains(x, y) && other.contains(x, y));
+                assert!(!overlap, "{:?} overlaps {:?}", grain, other);
+            }
+        }
+    }
+}
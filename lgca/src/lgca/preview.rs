@@ -0,0 +1,244 @@
+//! Live preview of a running simulation in the browser.
+//!
+//! The first rank serves a small page on localhost with the latest frame of the whole grid and the statistics of
+//! the run, so long runs can be watched long before the WebP is written at the end.
+//! Every `interval` rounds all ranks render their rows and send them to the first rank, which stitches them together.
+use mpi::traits::*;
+use ril::{Image, ImageFormat, Rgb};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{
+    output::{render_scaled, Output},
+    simulation::Simulation,
+};
+
+/// What the server shows, updated by the simulation
+#[derive(Default)]
+struct PreviewState {
+    /// Latest frame as PNG, empty before the first one
+    frame: Vec<u8>,
+    /// Statistics of the last round as JSON
+    statistics: String,
+}
+
+/// Serves the latest frame and statistics over HTTP. Only the first rank runs a server
+pub struct LivePreview {
+    port: u16,
+    interval: usize,
+    scaling: f64,
+    started: Instant,
+    state: Arc<Mutex<PreviewState>>,
+    address: Option<SocketAddr>,
+}
+
+impl LivePreview {
+    /// Serve on `127.0.0.1:port` and render a frame every `interval` rounds. Port 0 picks a free port
+    pub fn new(port: u16, interval: usize, scaling: f64) -> Self {
+        assert!(
+            interval > 0,
+            "The preview needs an interval of at least one round"
+        );
+        Self {
+            port,
+            interval,
+            scaling,
+            started: Instant::now(),
+            state: Arc::default(),
+            address: None,
+        }
+    }
+
+    /// Address of the server, once the first rank started it
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.address
+    }
+
+    fn start_server(&mut self) {
+        let listener = TcpListener::bind(("127.0.0.1", self.port)).unwrap_or_else(|error| {
+            panic!("Can not serve the preview on port {}: {}", self.port, error)
+        });
+        let address = listener.local_addr().unwrap();
+        eprintln!("Live preview on http://{}/", address);
+        self.address = Some(address);
+
+        let state = Arc::clone(&self.state);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A broken connection only affects its browser
+                let _ = respond(stream, &state);
+            }
+        });
+    }
+
+    /// Render the rows of all ranks into one image on the first rank. Collective
+    fn gather_frame<const WIDTH: usize>(
+        &self,
+        simulation: &Simulation<WIDTH>,
+    ) -> Option<Image<Rgb>> {
+        let image = render_scaled(simulation, self.scaling);
+        let Some(communicator) = simulation.communicator() else {
+            return Some(image);
+        };
+        let pixels: Vec<u8> = image.data.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+        if communicator.rank() != 0 {
+            communicator.process_at_rank(0).send(&pixels[..]);
+            return None;
+        }
+
+        // Strips can differ in height, so every strip is received on its own and placed below the previous one
+        let width = image.width() as usize;
+        let mut all = pixels;
+        for strip in 1..communicator.size() {
+            all.extend(communicator.process_at_rank(strip).receive_vec::<u8>().0);
+        }
+        let pixels = all
+            .chunks_exact(3)
+            .map(|pixel| Rgb::new(pixel[0], pixel[1], pixel[2]))
+            .collect::<Vec<_>>();
+        Some(Image::from_pixels(width as u32, pixels))
+    }
+
+    fn statistics<const WIDTH: usize>(&self, simulation: &Simulation<WIDTH>) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let timings = &simulation.timings;
+        format!(
+            concat!(
+                "{{\"round\":{},\"width\":{},\"height\":{},\"ranks\":{},\"elapsed\":{},",
+                "\"rounds_per_second\":{},\"timings\":{{\"core\":{},\"top_bottom\":{},",
+                "\"communication\":{},\"render\":{}}}}}"
+            ),
+            simulation.round(),
+            WIDTH,
+            simulation.global_height(),
+            simulation.size(),
+            elapsed,
+            simulation.round() as f64 / elapsed.max(1e-9),
+            timings.core.as_secs_f64(),
+            timings.top_bottom.as_secs_f64(),
+            timings.communication.as_secs_f64(),
+            timings.render.as_secs_f64()
+        )
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for LivePreview {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        let first = simulation.rank() == 0;
+        if first && self.address.is_none() {
+            self.start_server();
+        }
+        let frame = if simulation.round() % self.interval == 0 {
+            self.gather_frame(simulation)
+        } else {
+            None
+        };
+        if !first {
+            return;
+        }
+
+        let mut png = Vec::new();
+        if let Some(frame) = frame {
+            frame.encode(ImageFormat::Png, &mut png).unwrap();
+        }
+        let statistics = self.statistics(simulation);
+        let mut state = self.state.lock().unwrap();
+        if !png.is_empty() {
+            state.frame = png;
+        }
+        state.statistics = statistics;
+    }
+}
+
+/// Answer a single request for the page, the frame or the statistics
+fn respond(mut stream: TcpStream, state: &Mutex<PreviewState>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Read the headers, closing a socket with unread data resets the connection
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, content_type, body) = {
+        let state = state.lock().unwrap();
+        match path {
+            "/" => ("200 OK", "text/html", page(&state.statistics).into_bytes()),
+            "/stats.json" => (
+                "200 OK",
+                "application/json",
+                state.statistics.clone().into_bytes(),
+            ),
+            "/frame.png" if !state.frame.is_empty() => ("200 OK", "image/png", state.frame.clone()),
+            "/frame.png" => (
+                "503 Service Unavailable",
+                "text/plain",
+                b"No frame yet".to_vec(),
+            ),
+            _ => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+/// Page that reloads itself every two seconds
+fn page(statistics: &str) -> String {
+    format!(
+        concat!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"2\">",
+            "<title>Lattice gas</title></head><body style=\"background:#222;color:#ddd;font-family:monospace\">",
+            "<pre>{}</pre><img src=\"/frame.png\" style=\"max-width:100%;image-rendering:pixelated\">",
+            "</body></html>"
+        ),
+        statistics.replace(',', ",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(address: SocketAddr, path: &str) -> Vec<u8> {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let start = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        response.split_off(start)
+    }
+
+    #[test]
+    fn serves_the_latest_frame_and_statistics() {
+        let mut simulation = Simulation::<16>::new(8, None);
+        simulation.add_seeded_noise(0.3, 1);
+        let mut preview = LivePreview::new(0, 2, 1.0);
+        simulation.run(3, &mut [&mut preview]);
+        let address = preview.address().unwrap();
+
+        let statistics = String::from_utf8(get(address, "/stats.json")).unwrap();
+        assert!(statistics.starts_with("{\"round\":3,\"width\":16,\"height\":8,"));
+        let frame = get(address, "/frame.png");
+        assert!(frame.starts_with(b"\x89PNG"));
+        let image = Image::<Rgb>::from_bytes(ImageFormat::Png, &frame[..]).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 8));
+        assert!(String::from_utf8(get(address, "/"))
+            .unwrap()
+            .contains("/frame.png"));
+        assert!(get(address, "/missing").starts_with(b"Not found"));
+    }
+}
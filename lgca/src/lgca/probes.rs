@@ -0,0 +1,257 @@
+//! Probes that record the flow at a few points every round.
+//!
+//! Full frames are too big to be written every round, but a time series at a few points behind an obstacle is enough
+//! to measure the frequency of vortex shedding. A probe averages the cells of a small rectangle, a single cell is
+//! very noisy. Every rank sums the cells of a probe it holds, the rank with the first row of the probe writes its file.
+use mpi::{collective::SystemOperation, traits::*};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use super::{
+    output::Output,
+    simulation::{strip_of_rank, Simulation},
+};
+
+/// A rectangle of cells at global coordinates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Probe {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FromStr for Probe {
+    type Err = String;
+
+    /// Parse `x,y` for a single cell or `x,y,width,height` for a region
+    fn from_str(description: &str) -> Result<Self, Self::Err> {
+        let numbers = description
+            .split(',')
+            .map(|number| number.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("Invalid probe {}: {}", description, error))?;
+        match numbers[..] {
+            [x, y] => Ok(Probe {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            }),
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Probe {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!(
+                "Invalid probe {}, expected x,y or x,y,width,height with a nonzero size",
+                description
+            )),
+        }
+    }
+}
+
+/// Flow in the region of a probe
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProbeSample {
+    /// Fraction of occupied channels
+    pub density: f64,
+    /// Mean momentum per cell. y points south
+    pub velocity: (f64, f64),
+}
+
+impl Probe {
+    /// Panic unless the probe lies inside a grid of `width` times `global_height` cells
+    pub fn assert_inside(&self, width: usize, global_height: usize) {
+        assert!(
+            self.x + self.width <= width && self.y + self.height <= global_height,
+            "Probe {:?} is not inside the grid of {}x{} cells",
+            self,
+            width,
+            global_height
+        );
+    }
+
+    /// Rank with the first row of the probe, which gets the samples
+    pub fn owner<const WIDTH: usize>(&self, simulation: &Simulation<WIDTH>) -> i32 {
+        let size = simulation.size();
+        (0..size)
+            .find(|rank| {
+                strip_of_rank(simulation.global_height(), *rank as usize, size as usize)
+                    .contains(&self.y)
+            })
+            .unwrap()
+    }
+
+    /// Whether the first row of the probe belongs to this rank
+    pub fn is_owned_by<const WIDTH: usize>(&self, simulation: &Simulation<WIDTH>) -> bool {
+        self.owner(simulation) == simulation.rank()
+    }
+
+    /// Average the cells of the probe. Collective, returns `None` on all ranks but the owner
+    pub fn sample<const WIDTH: usize>(
+        &self,
+        simulation: &Simulation<WIDTH>,
+    ) -> Option<ProbeSample> {
+        self.assert_inside(WIDTH, simulation.global_height());
+
+        // Particles and momentum of the rows of the probe on this rank
+        let row_offset = simulation.row_offset();
+        let rows =
+            self.y.max(row_offset)..(self.y + self.height).min(row_offset + simulation.height());
+        let mut sums = [0.0; 3];
+        for y in rows {
+            for cell in &simulation.grid()[y - row_offset][self.x..self.x + self.width] {
+                let (momentum_x, momentum_y) = cell.get_momentum();
+                sums[0] += cell.get_particles() as f64;
+                sums[1] += momentum_x as f64;
+                sums[2] += momentum_y as f64;
+            }
+        }
+
+        if let Some(communicator) = simulation.communicator() {
+            let owner = communicator.process_at_rank(self.owner(simulation));
+            if !self.is_owned_by(simulation) {
+                owner.reduce_into(&sums[..], SystemOperation::sum());
+                return None;
+            }
+            let mut total = [0.0; 3];
+            owner.reduce_into_root(&sums[..], &mut total[..], SystemOperation::sum());
+            sums = total;
+        }
+
+        let cells = (self.width * self.height) as f64;
+        Some(ProbeSample {
+            density: sums[0] / (6.0 * cells),
+            velocity: (sums[1] / cells, sums[2] / cells),
+        })
+    }
+}
+
+/// Samples every probe after every round and writes the time series into `probe_<index>.csv`
+pub struct Probes {
+    pub probes: Vec<Probe>,
+    directory: PathBuf,
+    files: Vec<Option<BufWriter<File>>>,
+    /// Last sample of every probe, `None` for the probes of other ranks
+    pub last: Vec<Option<ProbeSample>>,
+}
+
+impl Probes {
+    pub fn new(probes: Vec<Probe>, directory: PathBuf) -> Self {
+        Self {
+            files: probes.iter().map(|_| None).collect(),
+            last: vec![None; probes.len()],
+            probes,
+            directory,
+        }
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for Probes {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        for (index, probe) in self.probes.iter().enumerate() {
+            let Some(sample) = probe.sample(simulation) else {
+                continue;
+            };
+            self.last[index] = Some(sample);
+
+            let file = self.files[index].get_or_insert_with(|| {
+                let path = self.directory.join(format!("probe_{}.csv", index));
+                let mut file = BufWriter::new(File::create(path).unwrap());
+                writeln!(file, "round,density,velocity_x,velocity_y").unwrap();
+                file
+            });
+            writeln!(
+                file,
+                "{},{},{},{}",
+                simulation.round(),
+                sample.density,
+                sample.velocity.0,
+                sample.velocity.1
+            )
+            .unwrap();
+        }
+    }
+
+    fn finish(&mut self) {
+        for file in self.files.iter_mut().flatten() {
+            file.flush().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{
+        cell::{TO_EAST, TO_WEST},
+        testing::TempDir,
+    };
+
+    #[test]
+    fn parse_probes() {
+        assert_eq!(
+            "3,4".parse(),
+            Ok(Probe {
+                x: 3,
+                y: 4,
+                width: 1,
+                height: 1
+            })
+        );
+        assert_eq!(
+            "3, 4, 5, 6".parse::<Probe>().map(|probe| probe.height),
+            Ok(6)
+        );
+        assert!("3".parse::<Probe>().is_err());
+        assert!("3,4,0,2".parse::<Probe>().is_err());
+        assert!("3,north".parse::<Probe>().is_err());
+    }
+
+    #[test]
+    fn probes_average_their_region_every_round() {
+        let mut simulation = Simulation::<12>::new(8, None);
+        for cell in &mut simulation.grid_mut()[3][2..6] {
+            cell.raw = TO_EAST;
+        }
+        let probe = Probe {
+            x: 2,
+            y: 3,
+            width: 2,
+            height: 2,
+        };
+        assert!(probe.is_owned_by(&simulation));
+        let sample = probe.sample(&simulation).unwrap();
+        assert_eq!(sample.density, 2.0 / 24.0);
+        assert_eq!(sample.velocity, (0.5, 0.0));
+
+        simulation.grid_mut()[4][2].raw = TO_WEST;
+        assert_eq!(probe.sample(&simulation).unwrap().velocity, (0.25, 0.0));
+
+        let directory = TempDir::new("probes");
+        let mut probes = Probes::new(
+            vec![probe, "0,0".parse().unwrap()],
+            directory.path().to_path_buf(),
+        );
+        simulation.run(4, &mut [&mut probes]);
+        Output::<12>::finish(&mut probes);
+
+        assert_eq!(probes.last[0], probe.sample(&simulation));
+        let lines = std::fs::read_to_string(directory.join("probe_1.csv")).unwrap();
+        assert_eq!(lines.lines().count(), 6);
+        assert!(lines.lines().last().unwrap().starts_with("4,"));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not inside the grid")]
+    fn probes_below_the_grid_are_rejected() {
+        let probe: Probe = "2,7,2,2".parse().unwrap();
+        probe.assert_inside(12, 8);
+    }
+}
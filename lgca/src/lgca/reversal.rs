@@ -0,0 +1,163 @@
+use mpi::{collective::SystemOperation, traits::*};
+use rayon::prelude::*;
+
+use super::{simulation::Simulation, Cell};
+
+/// Chirality of a cell in a round. Only depends on the global position, so it is the same for any number of ranks
+pub fn chirality(seed: u64, x: usize, y: usize, round: usize) -> bool {
+    // splitmix64 of all inputs
+    let mut hash = seed
+        ^ (x as u64).wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ (round as u64).wrapping_mul(0x165667B19E3779F9);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D049BB133111EB);
+    hash ^= hash >> 31;
+    hash & 1 != 0
+}
+
+/// Turn every particle around
+pub fn reverse_velocities<const WIDTH: usize>(grid: &mut [[Cell; WIDTH]]) {
+    grid.par_iter_mut().flatten().for_each(|cell| {
+        cell.raw = ((cell.raw << 3) | (cell.raw >> 3)) & 0b00111111;
+    });
+}
+
+/// Runs a simulation forward and backward with deterministic, invertible collisions.
+///
+/// A forward round moves the particles through the normal movement kernels without collisions and collides them
+/// with `Cell::process_chiral_collision`. The inverse round undoes the collisions with the opposite chirality
+/// and moves the reversed particles forward, which is the same as moving them backward.
+/// Any particle that gets lost or duplicated at a border or between ranks shows up as a difference
+/// to the initial state.
+///
+/// The collisions of `step` are not checked. The cells at the ends of the rows and in the top and bottom row
+/// collide randomly, so a round of `step` can not be undone.
+pub struct TimeReversal {
+    pub seed: u64,
+}
+
+impl TimeReversal {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn collide<const WIDTH: usize>(
+        &self,
+        simulation: &mut Simulation<WIDTH>,
+        round: usize,
+        inverse: bool,
+    ) {
+        let row_offset = simulation.row_offset();
+        simulation
+            .grid_mut()
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    let chirality = chirality(self.seed, x, y + row_offset, round);
+                    cell.process_chiral_collision(chirality != inverse);
+                }
+            });
+    }
+
+    /// Calculate round `round` forward
+    pub fn forward<const WIDTH: usize>(&self, simulation: &mut Simulation<WIDTH>, round: usize)
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        simulation.propagate();
+        self.collide(simulation, round, false);
+    }
+
+    /// Undo round `round`
+    pub fn backward<const WIDTH: usize>(&self, simulation: &mut Simulation<WIDTH>, round: usize)
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        self.collide(simulation, round, true);
+        reverse_velocities(simulation.grid_mut());
+        simulation.propagate();
+        reverse_velocities(simulation.grid_mut());
+    }
+
+    /// Run `rounds` rounds forward and backward again.
+    ///
+    /// Returns the number of cells on all ranks that differ from the initial state. Zero means the run was exact.
+    pub fn run<const WIDTH: usize>(&self, simulation: &mut Simulation<WIDTH>, rounds: usize) -> u64
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        let initial = simulation.grid().to_vec();
+        for round in 0..rounds {
+            self.forward(simulation, round);
+        }
+        for round in (0..rounds).rev() {
+            self.backward(simulation, round);
+        }
+
+        let differences = simulation
+            .grid()
+            .iter()
+            .flatten()
+            .zip(initial.iter().flatten())
+            .filter(|(cell, initial)| cell != initial)
+            .count() as u64;
+        let Some(communicator) = simulation.communicator() else {
+            return differences;
+        };
+        let mut total_differences = 0u64;
+        communicator.all_reduce_into(&differences, &mut total_differences, SystemOperation::sum());
+        total_differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chiral_collision_is_undone_by_opposite_chirality() {
+        for raw in 0..64u8 {
+            for chirality in [false, true] {
+                let mut cell = Cell { raw };
+                cell.process_chiral_collision(chirality);
+                assert_eq!(cell.get_particles(), Cell { raw }.get_particles());
+                assert_eq!(cell.get_momentum(), Cell { raw }.get_momentum());
+                cell.process_chiral_collision(!chirality);
+                assert_eq!(cell.raw, raw);
+            }
+        }
+    }
+
+    #[test]
+    fn reversal_restores_initial_state() {
+        let mut simulation = Simulation::<16>::new(12, None);
+        simulation.fill_box(6);
+        simulation.add_noise(0.3);
+        let initial = simulation.grid().to_vec();
+        let reversal = TimeReversal::new(7);
+
+        for round in 0..40 {
+            reversal.forward(&mut simulation, round);
+        }
+        assert_ne!(simulation.grid(), &initial[..]);
+        for round in (0..40).rev() {
+            reversal.backward(&mut simulation, round);
+        }
+        assert_eq!(simulation.grid(), &initial[..]);
+
+        assert_eq!(reversal.run(&mut simulation, 25), 0);
+    }
+
+    #[test]
+    fn reversal_works_with_an_odd_number_of_rows() {
+        let mut simulation = Simulation::<16>::new(13, None);
+        simulation.fill_box(8);
+        simulation.add_noise(0.3);
+        assert_eq!(TimeReversal::new(3).run(&mut simulation, 30), 0);
+    }
+}
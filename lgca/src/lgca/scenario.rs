@@ -0,0 +1,100 @@
+use std::str::FromStr;
+
+use super::{
+    forcing::BodyForce,
+    simulation::{Simulation, Timings},
+};
+
+/// Initial state and forcing of a simulation, described by a string like `box=500 noise=0.04`.
+///
+/// Pairs are separated by whitespace, commas or semicolons. Missing keys keep their default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    /// Size of the initially filled box in the north west corner
+    pub boxx: usize,
+    /// Probability that a channel gets flipped by the initial noise
+    pub noise: f64,
+    /// Probability per cell and round that a particle gets flipped to the east
+    pub body_force: f64,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            boxx: 0,
+            noise: 0.0,
+            body_force: 0.0,
+        }
+    }
+}
+
+impl Scenario {
+    /// Reset the particles, the round counter and the timings of the simulation and set it up for this scenario
+    pub fn apply<const WIDTH: usize>(&self, simulation: &mut Simulation<WIDTH>) {
+        simulation.clear();
+        simulation.set_round(0);
+        simulation.timings = Timings::default();
+        simulation.fill_box(self.boxx);
+        simulation.add_noise(self.noise);
+        simulation.set_body_force(BodyForce::new(self.body_force));
+    }
+}
+
+fn parse_probability(key: &str, value: &str) -> Result<f64, String> {
+    let probability: f64 = value
+        .parse()
+        .map_err(|_| format!("{} must be a number, got {}", key, value))?;
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(format!("{} must be between 0 and 1, got {}", key, value));
+    }
+    Ok(probability)
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    fn from_str(description: &str) -> Result<Self, Self::Err> {
+        let mut scenario = Scenario::default();
+        for pair in description
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|pair| !pair.is_empty())
+        {
+            let Some((key, value)) = pair.split_once('=') else {
+                return Err(format!("Expected key=value, got {}", pair));
+            };
+            match key {
+                "box" => {
+                    scenario.boxx = value
+                        .parse()
+                        .map_err(|_| format!("box must be a size, got {}", value))?
+                }
+                "noise" => scenario.noise = parse_probability(key, value)?,
+                "body_force" => scenario.body_force = parse_probability(key, value)?,
+                _ => return Err(format!("Unknown scenario key {}", key)),
+            }
+        }
+        Ok(scenario)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scenario() {
+        let scenario: Scenario = "box=20, noise=0.1;body_force=0.01".parse().unwrap();
+        assert_eq!(
+            scenario,
+            Scenario {
+                boxx: 20,
+                noise: 0.1,
+                body_force: 0.01,
+            }
+        );
+        assert_eq!("".parse::<Scenario>().unwrap(), Scenario::default());
+        assert!("box".parse::<Scenario>().is_err());
+        assert!("noise=2".parse::<Scenario>().is_err());
+        assert!("speed=3".parse::<Scenario>().is_err());
+    }
+}
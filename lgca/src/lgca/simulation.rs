@@ -0,0 +1,973 @@
+use mpi::{
+    collective::SystemOperation, request::WaitGuard, topology::SimpleCommunicator, traits::*, Tag,
+};
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use super::{
+    boundary::{Boundary, ReflectingBoundary},
+    cell::{
+        as_bytes, as_bytes_mut, TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST,
+        TO_SOUTH_WEST, TO_WEST,
+    },
+    fields::CoarseFields,
+    forcing::BodyForce,
+    memory::allocate_rows,
+    new_movements::{movement_even_row, movement_odd_row},
+    obstacles::{Obstacles, Shape},
+    output::Output,
+    species::collide_species,
+    tiling::TemporalBlocking,
+    Cell,
+};
+
+/// Time spent in the different parts of the simulation
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Timings {
+    pub top_bottom: Duration,
+    pub core: Duration,
+    pub communication: Duration,
+    pub render: Duration,
+}
+
+impl Timings {
+    pub fn calculation(&self) -> Duration {
+        self.core + self.top_bottom
+    }
+}
+
+/// Draws of `set_species`, kept apart from the draws of `add_seeded_noise` with the same seed
+const SPECIES_STREAM: u64 = 0xD1B5_4A32_D192_ED03;
+
+/// Random numbers for the row `global_row`, so rows can be filled in parallel independent of ranks and threads
+fn row_random(seed: u64, global_row: usize) -> SmallRng {
+    SmallRng::seed_from_u64(seed ^ (global_row as u64).wrapping_mul(0x9E3779B97F4A7C15))
+}
+
+/// Rows of a grid with `height` rows that belong to `rank`, if the grid is split into `size` strips.
+///
+/// The strips differ by at most one row, the first ranks get the longer ones.
+pub fn strip_of_rank(height: usize, rank: usize, size: usize) -> Range<usize> {
+    let rows = height / size;
+    let longer_strips = height % size;
+    assert!(
+        rows >= 2,
+        "{} rows can not be split into {} strips with at least 2 rows",
+        height,
+        size
+    );
+    let start = rank * rows + rank.min(longer_strips);
+    let end = start + rows + usize::from(rank < longer_strips);
+    start..end
+}
+
+/// Send `first_row` to the previous and `last_row` to the next rank and receive their border rows.
+///
+/// `receive` holds the buffers for the rows of the previous and next rank in `ranks`, `None` if there is no such rank.
+/// With periodic rows the first and last rank are neighbors, and a single rank is its own neighbor.
+/// Without a communicator this can only be the case, so the rows are copied.
+fn exchange_rows<const WIDTH: usize>(
+    communicator: Option<&SimpleCommunicator>,
+    ranks: [i32; 2],
+    first_row: &[Cell; WIDTH],
+    last_row: &[Cell; WIDTH],
+    receive: &mut [Option<&mut [Cell; WIDTH]>; 2],
+) {
+    let [receive_top, receive_bottom] = receive;
+    let Some(communicator) = communicator else {
+        if let Some(receive_top) = receive_top {
+            receive_top.copy_from_slice(last_row);
+        }
+        if let Some(receive_bottom) = receive_bottom {
+            receive_bottom.copy_from_slice(first_row);
+        }
+        return;
+    };
+    // Tell the rows apart by their direction, both neighbors are the same rank with two ranks and periodic rows
+    const UPWARDS: Tag = 0;
+    const DOWNWARDS: Tag = 1;
+    mpi::request::scope(|scope| {
+        let mut guards = Vec::new();
+
+        if let Some(receive_top) = receive_top {
+            let process = communicator.process_at_rank(ranks[0]);
+            guards.push(WaitGuard::from(process.immediate_send_with_tag(
+                scope,
+                as_bytes(first_row),
+                UPWARDS,
+            )));
+            guards.push(WaitGuard::from(process.immediate_receive_into_with_tag(
+                scope,
+                as_bytes_mut(receive_top),
+                DOWNWARDS,
+            )));
+        }
+
+        if let Some(receive_bottom) = receive_bottom {
+            let process = communicator.process_at_rank(ranks[1]);
+            guards.push(WaitGuard::from(process.immediate_send_with_tag(
+                scope,
+                as_bytes(last_row),
+                DOWNWARDS,
+            )));
+            guards.push(WaitGuard::from(process.immediate_receive_into_with_tag(
+                scope,
+                as_bytes_mut(receive_bottom),
+                UPWARDS,
+            )));
+        }
+    });
+}
+
+/// Calculate a row between the top and bottom row of the grid with the kernel for its parity
+fn movement_inner_row<const WIDTH: usize>(
+    boundary: &dyn Boundary<WIDTH>,
+    even: bool,
+    above: &[Cell; WIDTH],
+    current: &[Cell; WIDTH],
+    below: &[Cell; WIDTH],
+    result: &mut [Cell; WIDTH],
+    collide: bool,
+) where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    match (even, collide) {
+        (true, true) => movement_even_row::<WIDTH, true>(above, current, below, result),
+        (true, false) => movement_even_row::<WIDTH, false>(above, current, below, result),
+        (false, true) => movement_odd_row::<WIDTH, true>(above, current, below, result),
+        (false, false) => movement_odd_row::<WIDTH, false>(above, current, below, result),
+    }
+    boundary.row_ends(even, above, current, below, result, collide);
+}
+
+/// Calculate a row of the next round with the kernel for its position.
+///
+/// The row above or below is `None` at the top and bottom of the whole grid.
+/// Without `collide` the particles only move.
+pub fn movement_row<const WIDTH: usize>(
+    boundary: &dyn Boundary<WIDTH>,
+    even: bool,
+    above: Option<&[Cell; WIDTH]>,
+    current: &[Cell; WIDTH],
+    below: Option<&[Cell; WIDTH]>,
+    result: &mut [Cell; WIDTH],
+    collide: bool,
+) where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    match (above, below) {
+        (None, Some(below)) => boundary.top_row(current, below, result, collide),
+        (Some(above), None) => boundary.bottom_row(above, current, result, even, collide),
+        (Some(above), Some(below)) => {
+            movement_inner_row(boundary, even, above, current, below, result, collide)
+        }
+        (None, None) => unreachable!("Every rank has at least 2 rows"),
+    }
+}
+
+/// A strip of rows of the whole grid.
+///
+/// With MPI every rank owns one strip and exchanges its first and last row with the neighboring ranks every round.
+/// Without a communicator the strip is the whole grid.
+pub struct Simulation<const WIDTH: usize> {
+    grid_a: Vec<[Cell; WIDTH]>,
+    /// Second grid the next round is calculated into. Allocated on first use, stays empty when updating in place
+    grid_b: Vec<[Cell; WIDTH]>,
+    in_place: bool,
+    huge_pages: bool,
+    receive_top: Box<[Cell; WIDTH]>,
+    receive_bottom: Box<[Cell; WIDTH]>,
+    /// Last and first row of every chunk boundary when updating in place, as they were before the round
+    halos: Vec<[Cell; WIDTH]>,
+    /// Old row above and new row of every chunk when updating in place
+    line_buffers: Vec<[Cell; WIDTH]>,
+    communicator: Option<SimpleCommunicator>,
+    rank: i32,
+    size: i32,
+    row_offset: usize,
+    global_height: usize,
+    round: usize,
+    boundary: Box<dyn Boundary<WIDTH>>,
+    body_force: BodyForce,
+    obstacles: Obstacles,
+    /// Red particles of two immiscible species as a second bit plane, empty with a single species
+    colors: Vec<[Cell; WIDTH]>,
+    pub timings: Timings,
+}
+
+impl<const WIDTH: usize> Simulation<WIDTH> {
+    pub fn rank(&self) -> i32 {
+        self.rank
+    }
+
+    /// Number of ranks the grid is split across
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    pub fn communicator(&self) -> Option<&SimpleCommunicator> {
+        self.communicator.as_ref()
+    }
+
+    /// Sum `local` element by element over all ranks. Collective, every rank gets the sums
+    pub fn sum_over_ranks<T: Equivalence + Copy>(&self, local: &[T]) -> Vec<T> {
+        let mut total = local.to_vec();
+        if let Some(communicator) = &self.communicator {
+            communicator.all_reduce_into(local, &mut total[..], SystemOperation::sum());
+        }
+        total
+    }
+
+    /// Number of rows on this rank
+    pub fn height(&self) -> usize {
+        self.grid_a.len()
+    }
+
+    /// Number of rows on all ranks together
+    pub fn global_height(&self) -> usize {
+        self.global_height
+    }
+
+    /// Global index of the first row on this rank
+    pub fn row_offset(&self) -> usize {
+        self.row_offset
+    }
+
+    /// Number of rounds calculated so far
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    /// Continue counting rounds from `round`, for example after loading a snapshot
+    pub fn set_round(&mut self, round: usize) {
+        self.round = round;
+    }
+
+    /// The rows of this rank
+    pub fn grid(&self) -> &[[Cell; WIDTH]] {
+        &self.grid_a
+    }
+
+    pub fn grid_mut(&mut self) -> &mut [[Cell; WIDTH]] {
+        &mut self.grid_a
+    }
+
+    /// The red particles of the rows of this rank, `None` with a single species
+    pub fn colors(&self) -> Option<&[[Cell; WIDTH]]> {
+        (!self.colors.is_empty()).then_some(&self.colors[..])
+    }
+
+    /// Split the particles into two immiscible species, every particle is red with a probability of `red_fraction`.
+    ///
+    /// The colors follow the particles from now on, so the grid should be filled before. Reproducible like
+    /// `add_seeded_noise`.
+    pub fn set_species(&mut self, red_fraction: f64, seed: u64) {
+        assert!(
+            self.boundary.deterministic(),
+            "The colors of two species can not follow particles at moving walls"
+        );
+        let row_offset = self.row_offset;
+        self.colors = self.grid_a.clone();
+        self.colors.par_iter_mut().enumerate().for_each(|(y, row)| {
+            let random = &mut row_random(seed ^ SPECIES_STREAM, row_offset + y);
+            for cell in row.iter_mut() {
+                for direction in [
+                    TO_EAST,
+                    TO_NORTH_EAST,
+                    TO_NORTH_WEST,
+                    TO_SOUTH_WEST,
+                    TO_SOUTH_EAST,
+                    TO_WEST,
+                ] {
+                    if cell.raw & direction != 0 && !random.gen_bool(red_fraction) {
+                        cell.raw ^= direction;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Number of particles on this rank
+    pub fn particles(&self) -> u64 {
+        self.grid_a
+            .iter()
+            .flatten()
+            .map(|cell| cell.get_particles() as u64)
+            .sum()
+    }
+
+    /// Density and velocity of this rank averaged over blocks
+    pub fn coarse_fields(&self, block_size: usize) -> CoarseFields {
+        CoarseFields::from_grid(&self.grid_a, block_size)
+    }
+
+    pub fn boundary(&self) -> &dyn Boundary<WIDTH> {
+        &*self.boundary
+    }
+
+    pub fn set_boundary(&mut self, boundary: Box<dyn Boundary<WIDTH>>) {
+        assert!(
+            !boundary.periodic_rows() || self.global_height % 2 == 0,
+            "Periodic rows need an even number of rows, otherwise the row parity breaks at the wrap"
+        );
+        assert!(
+            self.colors.is_empty() || boundary.deterministic(),
+            "The colors of two species can not follow particles at moving walls"
+        );
+        self.boundary = boundary;
+    }
+
+    pub fn set_body_force(&mut self, body_force: BodyForce) {
+        self.body_force = body_force;
+    }
+
+    pub fn obstacles(&self) -> &Obstacles {
+        &self.obstacles
+    }
+
+    /// Put solid bodies into the flow and remove the particles inside them
+    pub fn set_obstacles(&mut self, shapes: Vec<Shape>) {
+        assert!(
+            !self.in_place,
+            "Obstacles need the old round in the second grid, they can not be combined with updates in place"
+        );
+        let rows = self.row_offset..self.row_offset + self.height();
+        self.obstacles = Obstacles::new(shapes, WIDTH, rows);
+        self.obstacles.clear(&mut self.grid_a);
+    }
+
+    /// Update the rows in place with a few line buffers instead of a second grid, which halves the memory
+    pub fn set_in_place(&mut self, in_place: bool) {
+        assert!(
+            !in_place || self.obstacles.is_empty(),
+            "Obstacles need the old round in the second grid, they can not be combined with updates in place"
+        );
+        self.in_place = in_place;
+        if in_place {
+            self.grid_b = Vec::new();
+        }
+    }
+
+    pub fn in_place(&self) -> bool {
+        self.in_place
+    }
+
+    fn allocate_second_grid(&mut self) {
+        assert!(!self.in_place, "The second grid is not used in place");
+        if self.grid_b.len() != self.grid_a.len() {
+            self.grid_b = allocate_rows(self.grid_a.len(), self.huge_pages);
+        }
+    }
+
+    /// Rank with the rows above, the last rank for the first one if the rows are periodic
+    pub fn previous_rank(&self) -> Option<i32> {
+        if self.rank > 0 {
+            Some(self.rank - 1)
+        } else if self.boundary.periodic_rows() {
+            Some(self.size - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Rank with the rows below, the first rank for the last one if the rows are periodic
+    pub fn next_rank(&self) -> Option<i32> {
+        if self.rank < self.size - 1 {
+            Some(self.rank + 1)
+        } else if self.boundary.periodic_rows() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn neighbor_ranks(&self) -> [i32; 2] {
+        [
+            self.previous_rank().unwrap_or(-1),
+            self.next_rank().unwrap_or(-1),
+        ]
+    }
+
+    /// Remove all particles
+    pub fn clear(&mut self) {
+        self.grid_a.par_iter_mut().flatten().for_each(|cell| {
+            cell.raw = 0;
+        });
+    }
+
+    /// Fill a box in the north west corner of the grid with particles in all directions
+    pub fn fill_box(&mut self, size: usize) {
+        let box_y = size.saturating_sub(self.row_offset()).min(self.height());
+        let box_x = size.min(WIDTH);
+        if self.rank == 0 {
+            for row in self.grid_a[..box_y].iter_mut() {
+                for cell in row[..box_x].iter_mut() {
+                    cell.raw = 0b00111111;
+                }
+            }
+        }
+    }
+
+    /// Flip every channel with a probability of `noise`
+    pub fn add_noise(&mut self, noise: f64) {
+        self.add_seeded_noise(noise, rand::random());
+    }
+
+    /// Same as `add_noise`, but reproducible.
+    ///
+    /// Every row draws from its own stream of `seed` and its global index, so the rows can be filled in parallel
+    /// and the noise does not depend on the number of ranks or threads.
+    pub fn add_seeded_noise(&mut self, noise: f64, seed: u64) {
+        let row_offset = self.row_offset;
+        self.grid_a.par_iter_mut().enumerate().for_each(|(y, row)| {
+            let random = &mut row_random(seed, row_offset + y);
+            for cell in row.iter_mut() {
+                for direction in [
+                    TO_EAST,
+                    TO_NORTH_EAST,
+                    TO_NORTH_WEST,
+                    TO_SOUTH_WEST,
+                    TO_SOUTH_EAST,
+                    TO_WEST,
+                ] {
+                    if random.gen_bool(noise) {
+                        cell.raw ^= direction;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Send the first and last row to the neighbors and receive their border rows
+    fn exchange_borders(&mut self) {
+        let height = self.grid_a.len();
+        let ranks = self.neighbor_ranks();
+        let (has_previous, has_next) = (self.previous_rank().is_some(), self.next_rank().is_some());
+        let mut receive = [
+            has_previous.then_some(&mut *self.receive_top),
+            has_next.then_some(&mut *self.receive_bottom),
+        ];
+        exchange_rows(
+            self.communicator.as_ref(),
+            ranks,
+            &self.grid_a[0],
+            &self.grid_a[height - 1],
+            &mut receive,
+        );
+    }
+
+    /// Show the current state to all outputs
+    pub fn observe(&mut self, outputs: &mut [&mut dyn Output<WIDTH>]) {
+        let timer = Instant::now();
+        for output in outputs.iter_mut() {
+            output.observe(self);
+        }
+        self.timings.render += timer.elapsed();
+    }
+}
+
+impl<const WIDTH: usize> Simulation<WIDTH>
+where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    /// Create an empty grid with `global_height` rows, split into strips across the ranks.
+    ///
+    /// Any height works, as long as every rank gets at least 2 rows.
+    pub fn new(global_height: usize, communicator: Option<SimpleCommunicator>) -> Self {
+        Self::new_with_huge_pages(global_height, communicator, false)
+    }
+
+    /// Same as `new`, but the grids are backed by transparent huge pages if `huge_pages` is set
+    pub fn new_with_huge_pages(
+        global_height: usize,
+        communicator: Option<SimpleCommunicator>,
+        huge_pages: bool,
+    ) -> Self {
+        let rank = communicator.as_ref().map_or(0, |c| c.rank());
+        let size = communicator.as_ref().map_or(1, |c| c.size());
+        let rows = strip_of_rank(global_height, rank as usize, size as usize);
+        let height = rows.len();
+
+        Self {
+            grid_a: allocate_rows(height, huge_pages),
+            grid_b: Vec::new(),
+            in_place: false,
+            huge_pages,
+            receive_top: Box::new([Cell::new(); WIDTH]),
+            receive_bottom: Box::new([Cell::new(); WIDTH]),
+            halos: Vec::new(),
+            line_buffers: Vec::new(),
+            communicator,
+            rank,
+            size,
+            row_offset: rows.start,
+            global_height,
+            round: 0,
+            boundary: Box::new(ReflectingBoundary),
+            body_force: BodyForce::new(0.0),
+            obstacles: Obstacles::new(Vec::new(), WIDTH, 0..0),
+            colors: Vec::new(),
+            timings: Timings::default(),
+        }
+    }
+
+    /// Calculate one round
+    pub fn step(&mut self) {
+        if !self.colors.is_empty() {
+            self.step_species();
+            return;
+        }
+        self.move_particles(true);
+
+        let round_timer = Instant::now();
+        self.body_force.apply(&mut self.grid_a);
+        if !self.obstacles.is_empty() {
+            let above = self.previous_rank().is_some().then_some(&*self.receive_top);
+            let below = self.next_rank().is_some().then_some(&*self.receive_bottom);
+            self.obstacles
+                .bounce_back(above, &self.grid_b, below, &mut self.grid_a);
+        }
+        self.timings.core += round_timer.elapsed();
+
+        self.round += 1;
+    }
+
+    /// One round of two species. The cells and colors move through the kernels without collisions,
+    /// then the cells collide with the rules of Rothman and Keller
+    fn step_species(&mut self) {
+        assert!(
+            !self.body_force.is_active() && self.obstacles.is_empty(),
+            "Two species can not be combined with a body force or obstacles"
+        );
+        self.move_particles(false);
+        std::mem::swap(&mut self.grid_a, &mut self.colors);
+        self.move_particles(false);
+
+        // The color field of the border rows needs the moved rows of the neighbors
+        let communication_time = Instant::now();
+        self.exchange_borders();
+        let colors_top = self.receive_top.clone();
+        let colors_bottom = self.receive_bottom.clone();
+        std::mem::swap(&mut self.grid_a, &mut self.colors);
+        self.exchange_borders();
+        self.timings.communication += communication_time.elapsed();
+
+        let round_timer = Instant::now();
+        let above = self
+            .previous_rank()
+            .is_some()
+            .then_some((&*self.receive_top, &*colors_top));
+        let below = self
+            .next_rank()
+            .is_some()
+            .then_some((&*self.receive_bottom, &*colors_bottom));
+        collide_species(
+            &mut self.grid_a,
+            &mut self.colors,
+            above,
+            below,
+            self.row_offset,
+            self.boundary.periodic_columns(),
+        );
+        self.timings.core += round_timer.elapsed();
+
+        self.round += 1;
+    }
+
+    /// Move all particles with the movement kernels, without counting a round. They collide if `collide` is set.
+    ///
+    /// The kernel of a row depends on the parity of its global index, strips can start with an odd row.
+    fn move_particles(&mut self, collide: bool) {
+        if self.in_place {
+            self.move_particles_in_place(collide);
+            return;
+        }
+        self.allocate_second_grid();
+        let height = self.grid_a.len();
+        let row_offset = self.row_offset;
+        let is_even = move |row_index: usize| (row_offset + row_index) % 2 == 0;
+
+        let communication_time = Instant::now();
+        self.exchange_borders();
+        self.timings.communication += communication_time.elapsed();
+
+        let round_timer = Instant::now();
+        let top = self.previous_rank().is_some().then_some(&*self.receive_top);
+        movement_row(
+            &*self.boundary,
+            is_even(0),
+            top,
+            &self.grid_a[0],
+            Some(&self.grid_a[1]),
+            &mut self.grid_b[0],
+            collide,
+        );
+        self.timings.top_bottom += round_timer.elapsed();
+
+        let round_timer = Instant::now();
+        let boundary = &*self.boundary;
+        self.grid_a
+            .par_windows(3)
+            .zip(self.grid_b.par_iter_mut().skip(1))
+            .enumerate()
+            .for_each(|(row_index, (context, result))| {
+                let above = &context[0];
+                let current = &context[1];
+                let below = &context[2];
+                let even = is_even(row_index + 1);
+                movement_inner_row(boundary, even, above, current, below, result, collide);
+            });
+        self.timings.core += round_timer.elapsed();
+
+        let round_timer = Instant::now();
+        let bottom = self.next_rank().is_some().then_some(&*self.receive_bottom);
+        movement_row(
+            &*self.boundary,
+            is_even(height - 1),
+            Some(&self.grid_a[height - 2]),
+            &self.grid_a[height - 1],
+            bottom,
+            &mut self.grid_b[height - 1],
+            collide,
+        );
+        std::mem::swap(&mut self.grid_a, &mut self.grid_b);
+        self.timings.top_bottom += round_timer.elapsed();
+    }
+
+    /// Same as `move_particles`, but overwrites the rows in place.
+    ///
+    /// The rows are split into one chunk per thread and every chunk is calculated from top to bottom.
+    /// The old state of the previous row is kept in a line buffer, the rows around the chunks are copied up front.
+    fn move_particles_in_place(&mut self, collide: bool) {
+        let height = self.grid_a.len();
+        let (has_previous, has_next) = (self.previous_rank().is_some(), self.next_rank().is_some());
+
+        let communication_time = Instant::now();
+        self.exchange_borders();
+        self.timings.communication += communication_time.elapsed();
+
+        let round_timer = Instant::now();
+        let chunk_height = height.div_ceil(rayon::current_num_threads());
+        self.halos.clear();
+        for start in (chunk_height..height).step_by(chunk_height) {
+            self.halos
+                .extend_from_slice(&self.grid_a[start - 1..start + 1]);
+        }
+        let last_chunk = self.halos.len() / 2;
+        // Only grows when there are more chunks than before
+        self.line_buffers
+            .resize(2 * (last_chunk + 1), [Cell::new(); WIDTH]);
+        let halos = &self.halos;
+        let top = has_previous.then_some(&*self.receive_top);
+        let bottom = has_next.then_some(&*self.receive_bottom);
+        let boundary = &*self.boundary;
+        let row_offset = self.row_offset;
+        let top_bottom: Duration = self
+            .grid_a
+            .par_chunks_mut(chunk_height)
+            .zip(self.line_buffers.par_chunks_exact_mut(2))
+            .enumerate()
+            .map(|(index, (rows, buffers))| {
+                let top = if index == 0 {
+                    top
+                } else {
+                    Some(&halos[2 * index - 2])
+                };
+                let bottom = if index == last_chunk {
+                    bottom
+                } else {
+                    Some(&halos[2 * index + 1])
+                };
+                let offset = row_offset + index * chunk_height;
+                let [previous, result] = buffers else {
+                    unreachable!("Every chunk has two line buffers")
+                };
+                let mut top_bottom = Duration::ZERO;
+                for y in 0..rows.len() {
+                    let is_border =
+                        (index == 0 && y == 0) || (index == last_chunk && y + 1 == rows.len());
+                    let timer = is_border.then(Instant::now);
+                    let above = if y == 0 { top } else { Some(&*previous) };
+                    let below = if y + 1 == rows.len() {
+                        bottom
+                    } else {
+                        Some(&rows[y + 1])
+                    };
+                    let even = (offset + y) % 2 == 0;
+                    movement_row(boundary, even, above, &rows[y], below, result, collide);
+                    // Keep the old row for the next one and put the new one in its place
+                    std::mem::swap(previous, &mut rows[y]);
+                    std::mem::swap(&mut rows[y], result);
+                    if let Some(timer) = timer {
+                        top_bottom += timer.elapsed();
+                    }
+                }
+                top_bottom
+            })
+            .sum();
+        // The first and last row are calculated by the threads of the first and last chunk
+        self.timings.top_bottom += top_bottom;
+        self.timings.core += round_timer.elapsed().saturating_sub(top_bottom);
+    }
+
+    /// Move all particles without any collisions, through the same kernels and borders as `step`.
+    ///
+    /// Deterministic unless the boundary has moving walls. Body force and obstacles are not applied.
+    pub fn propagate(&mut self) {
+        self.move_particles(false);
+    }
+
+    /// Calculate `rounds` rounds at once with temporal blocking, at most `blocking.depth`.
+    ///
+    /// Same as calling `step` `rounds` times, but the rows of a tile stay in the cache for all rounds.
+    pub fn step_blocked(&mut self, blocking: &TemporalBlocking, rounds: usize) {
+        assert!(
+            self.obstacles.is_empty() && self.colors.is_empty(),
+            "Temporal blocking can not be combined with obstacles or two species"
+        );
+        self.allocate_second_grid();
+        let (has_previous, has_next) = (self.previous_rank().is_some(), self.next_rank().is_some());
+        let row_offset = self.row_offset;
+        let boundary = &*self.boundary;
+        let body_force = self.body_force;
+        let movement = |y: usize,
+                        above: Option<&[Cell; WIDTH]>,
+                        current: &[Cell; WIDTH],
+                        below: Option<&[Cell; WIDTH]>,
+                        result: &mut [Cell; WIDTH]| {
+            let even = (row_offset + y) % 2 == 0;
+            movement_row(boundary, even, above, current, below, result, true);
+            body_force.apply_row(result);
+        };
+
+        let mut communication = Duration::ZERO;
+        let communicator = self.communicator.as_ref();
+        let ranks = self.neighbor_ranks();
+        let exchange = |first_row: &[Cell; WIDTH],
+                        last_row: &[Cell; WIDTH],
+                        receive: &mut [Option<&mut [Cell; WIDTH]>; 2]| {
+            let timer = Instant::now();
+            exchange_rows(communicator, ranks, first_row, last_row, receive);
+            communication += timer.elapsed();
+        };
+
+        let timer = Instant::now();
+        let receive = [
+            has_previous.then_some(&mut *self.receive_top),
+            has_next.then_some(&mut *self.receive_bottom),
+        ];
+        blocking.advance(
+            [&mut self.grid_a, &mut self.grid_b],
+            receive,
+            rounds,
+            movement,
+            exchange,
+        );
+        if rounds % 2 == 1 {
+            std::mem::swap(&mut self.grid_a, &mut self.grid_b);
+        }
+        self.timings.core += timer.elapsed() - communication;
+        self.timings.communication += communication;
+        self.round += rounds;
+    }
+
+    /// Calculate `rounds` rounds in blocks of `blocking.depth` rounds. Outputs are not supported,
+    /// because the intermediate rounds are never complete at the same time
+    pub fn run_blocked(&mut self, blocking: &TemporalBlocking, rounds: usize) {
+        let mut remaining = rounds;
+        while remaining > 0 {
+            let block = remaining.min(blocking.depth);
+            self.step_blocked(blocking, block);
+            remaining -= block;
+        }
+    }
+
+    /// Calculate `rounds` rounds and show every state to the outputs.
+    ///
+    /// The initial state is also shown, if no round was calculated before.
+    pub fn run(&mut self, rounds: usize, outputs: &mut [&mut dyn Output<WIDTH>]) {
+        if self.round == 0 {
+            self.observe(outputs);
+        }
+        for _ in 0..rounds {
+            self.step();
+            self.observe(outputs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{
+        boundary::{Edge, EdgeBoundary},
+        cell::RNG,
+    };
+
+    struct ParticleCounter {
+        counts: Vec<u64>,
+    }
+
+    impl<const WIDTH: usize> Output<WIDTH> for ParticleCounter {
+        fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+            self.counts.push(simulation.particles());
+        }
+    }
+
+    #[test]
+    fn run_keeps_particles_and_calls_outputs() {
+        let mut simulation = Simulation::<20>::new(10, None);
+        simulation.fill_box(5);
+        let particles = simulation.particles();
+
+        let mut counter = ParticleCounter { counts: Vec::new() };
+        simulation.run(30, &mut [&mut counter]);
+
+        assert_eq!(simulation.round(), 30);
+        assert_eq!(counter.counts.len(), 31);
+        assert!(counter.counts.iter().all(|count| *count == particles));
+    }
+
+    #[test]
+    fn strips_differ_by_at_most_one_row() {
+        let strips: Vec<_> = (0..3).map(|rank| strip_of_rank(11, rank, 3)).collect();
+        assert_eq!(strips, vec![0..4, 4..8, 8..11]);
+        assert_eq!(strip_of_rank(12, 3, 4), 9..12);
+    }
+
+    #[test]
+    fn odd_height_keeps_a_full_grid_full() {
+        let mut simulation = Simulation::<12>::new(7, None);
+        simulation.fill_box(12);
+        assert_eq!(simulation.particles(), 12 * 7 * 6);
+        simulation.step();
+        simulation.propagate();
+        assert!(simulation
+            .grid()
+            .iter()
+            .flatten()
+            .all(|cell| cell.raw == 0b00111111));
+    }
+
+    #[test]
+    fn blocked_steps_keep_particles() {
+        let mut simulation = Simulation::<12>::new(23, None);
+        simulation.fill_box(8);
+        simulation.add_noise(0.2);
+        let particles = simulation.particles();
+        simulation.run_blocked(&TemporalBlocking::new(6, 3), 20);
+        assert_eq!(simulation.round(), 20);
+        assert_eq!(simulation.particles(), particles);
+
+        simulation.fill_box(23);
+        simulation.step_blocked(&TemporalBlocking::new(8, 4), 3);
+        assert!(simulation
+            .grid()
+            .iter()
+            .flatten()
+            .all(|cell| cell.raw == 0b00111111));
+    }
+
+    #[test]
+    fn in_place_steps_like_two_grids() {
+        let edges = [
+            [Edge::Reflecting; 4],
+            [Edge::Periodic, Edge::Periodic, Edge::BounceBack, Edge::Open],
+        ];
+        for [north, south, west, east] in edges {
+            for threads in 1..=4 {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    let mut two_grids = Simulation::<12>::new(22, None);
+                    two_grids.set_boundary(Box::new(EdgeBoundary::new(north, south, west, east)));
+                    two_grids.fill_box(6);
+                    two_grids.add_seeded_noise(0.3, threads as u64);
+                    let mut in_place = Simulation::<12>::new(22, None);
+                    in_place.set_boundary(Box::new(EdgeBoundary::new(north, south, west, east)));
+                    in_place.grid_mut().copy_from_slice(two_grids.grid());
+                    in_place.set_in_place(true);
+
+                    for round in 0..10 {
+                        // The edges collide randomly. On a single thread both draw the same numbers in the same
+                        // order, with more threads only the movement can be compared
+                        if threads == 1 {
+                            RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(round));
+                            two_grids.step();
+                            RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(round));
+                            in_place.step();
+                        } else {
+                            two_grids.propagate();
+                            in_place.propagate();
+                        }
+                        assert_eq!(
+                            in_place.grid(),
+                            two_grids.grid(),
+                            "Round {} with {} threads",
+                            round,
+                            threads
+                        );
+                    }
+                    assert!(in_place.grid_b.is_empty());
+                    assert!(in_place.timings.top_bottom > Duration::ZERO);
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn red_fraction_does_not_depend_on_the_noise() {
+        for noise in [0.1, 0.2, 0.4, 0.8] {
+            let mut simulation = Simulation::<16>::new(1000, None);
+            simulation.add_seeded_noise(noise, 3);
+            simulation.set_species(0.5, 3);
+            let colors = simulation.colors().unwrap();
+            let count = |cells: &[[Cell; 16]]| -> u32 {
+                cells
+                    .iter()
+                    .flatten()
+                    .map(|cell| cell.raw.count_ones())
+                    .sum()
+            };
+            let red = count(colors) as f64 / count(simulation.grid()) as f64;
+            assert!((red - 0.5).abs() < 0.02, "{} red with noise {}", red, noise);
+            // With a shared stream the first draw of a row decides both whether the first channel is occupied
+            // and whether its particle is red
+            let (first, first_red) = simulation
+                .grid()
+                .iter()
+                .zip(colors)
+                .filter(|(row, _)| row[0].raw & TO_EAST != 0)
+                .fold((0, 0), |(first, red), (_, colors)| {
+                    (first + 1, red + usize::from(colors[0].raw & TO_EAST != 0))
+                });
+            let red = first_red as f64 / first as f64;
+            assert!(
+                (red - 0.5).abs() < 0.15,
+                "{} of the first particles red with noise {}",
+                red,
+                noise
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_noise_is_reproducible() {
+        let noisy = |seed| {
+            let mut simulation = Simulation::<16>::new(30, None);
+            simulation.add_seeded_noise(0.3, seed);
+            simulation.grid().to_vec()
+        };
+        assert_eq!(noisy(4), noisy(4));
+        assert_ne!(noisy(4), noisy(5));
+    }
+}
@@ -0,0 +1,327 @@
+//! Snapshots of the whole grid in a single file.
+//!
+//! The file starts with a `SnapshotHeader`, followed by all rows of raw cell bytes in global order.
+//! With MPI every rank writes and reads its own rows of the shared file with collective MPI-IO calls,
+//! so a snapshot can be loaded with a different number of ranks than it was written with.
+use mpi::{ffi, topology::SimpleCommunicator, traits::*};
+use std::{
+    ffi::{c_int, c_void, CString},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    output::Output,
+    simulation::{strip_of_rank, Simulation},
+};
+
+/// Self describing start of a snapshot file. All numbers are little endian
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SnapshotHeader {
+    /// Cells per row
+    pub width: u64,
+    /// Number of rows of the whole grid
+    pub height: u64,
+    /// Round the snapshot was taken after
+    pub round: u64,
+}
+
+impl SnapshotHeader {
+    pub const MAGIC: [u8; 8] = *b"LGCASNAP";
+    pub const VERSION: u32 = 1;
+    /// Size of the header in bytes. The rows start right after it
+    pub const SIZE: usize = 40;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&Self::MAGIC);
+        bytes[8..12].copy_from_slice(&Self::VERSION.to_le_bytes());
+        bytes[12..16].copy_from_slice(&(Self::SIZE as u32).to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.width.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.height.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.round.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Result<Self, String> {
+        if bytes[0..8] != Self::MAGIC {
+            return Err("Not a snapshot file".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != Self::VERSION {
+            return Err(format!("Unsupported snapshot version {}", version));
+        }
+        let header_size = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        if header_size as usize != Self::SIZE {
+            return Err(format!("Unexpected header size {}", header_size));
+        }
+        Ok(Self {
+            width: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            height: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            round: u64::from_le_bytes(bytes[32..40].try_into().unwrap()),
+        })
+    }
+
+    /// Size of the whole file in bytes
+    pub fn file_size(&self) -> u64 {
+        Self::SIZE as u64 + self.width * self.height
+    }
+}
+
+fn check(code: c_int, function: &str) {
+    if code != ffi::MPI_SUCCESS as c_int {
+        panic!("{} failed with MPI error code {}", function, code);
+    }
+}
+
+/// Split the rows into chunks that fit into the `int` count of MPI calls.
+///
+/// Every rank makes as many collective calls as the rank with the longest strip, `longest` rows.
+/// Ranks with shorter strips take part in the last calls with empty chunks.
+fn row_chunks<const WIDTH: usize>(
+    rows: usize,
+    longest: usize,
+) -> impl Iterator<Item = Range<usize>> {
+    let chunk = (c_int::MAX as usize / WIDTH).max(1);
+    (0..longest)
+        .step_by(chunk)
+        .map(move |start| start.min(rows)..(start + chunk).min(rows))
+}
+
+unsafe fn open_file(communicator: &SimpleCommunicator, path: &Path, mode: u32) -> ffi::MPI_File {
+    let filename = CString::new(path.to_str().unwrap()).unwrap();
+    let mut file = std::mem::MaybeUninit::<ffi::MPI_File>::uninit();
+    check(
+        ffi::MPI_File_open(
+            communicator.as_raw(),
+            filename.as_ptr(),
+            mode as c_int,
+            ffi::RSMPI_INFO_NULL,
+            file.as_mut_ptr(),
+        ),
+        "MPI_File_open",
+    );
+    file.assume_init()
+}
+
+/// Write the whole grid into one file. With MPI this is collective, every rank has to call it
+pub fn write_snapshot<const WIDTH: usize>(simulation: &Simulation<WIDTH>, path: &Path) {
+    let header = SnapshotHeader {
+        width: WIDTH as u64,
+        height: simulation.global_height() as u64,
+        round: simulation.round() as u64,
+    };
+    let grid = simulation.grid();
+
+    let Some(communicator) = simulation.communicator() else {
+        let mut bytes = header.to_bytes().to_vec();
+        bytes.extend(grid.iter().flatten().map(|cell| cell.raw));
+        std::fs::write(path, bytes).unwrap();
+        return;
+    };
+
+    unsafe {
+        let mut file = open_file(
+            communicator,
+            path,
+            ffi::MPI_MODE_CREATE | ffi::MPI_MODE_WRONLY,
+        );
+        // Cut off the rest of an older, larger snapshot
+        check(
+            ffi::MPI_File_set_size(file, header.file_size() as ffi::MPI_Offset),
+            "MPI_File_set_size",
+        );
+        if simulation.rank() == 0 {
+            let bytes = header.to_bytes();
+            check(
+                ffi::MPI_File_write_at(
+                    file,
+                    0,
+                    bytes.as_ptr() as *const c_void,
+                    bytes.len() as c_int,
+                    ffi::RSMPI_UINT8_T,
+                    ffi::RSMPI_STATUS_IGNORE,
+                ),
+                "MPI_File_write_at",
+            );
+        }
+        let longest =
+            strip_of_rank(simulation.global_height(), 0, simulation.size() as usize).len();
+        for rows in row_chunks::<WIDTH>(grid.len(), longest) {
+            let offset = SnapshotHeader::SIZE + (simulation.row_offset() + rows.start) * WIDTH;
+            check(
+                ffi::MPI_File_write_at_all(
+                    file,
+                    offset as ffi::MPI_Offset,
+                    grid[rows.clone()].as_ptr() as *const c_void,
+                    (rows.len() * WIDTH) as c_int,
+                    ffi::RSMPI_UINT8_T,
+                    ffi::RSMPI_STATUS_IGNORE,
+                ),
+                "MPI_File_write_at_all",
+            );
+        }
+        check(ffi::MPI_File_close(&mut file), "MPI_File_close");
+    }
+}
+
+/// Load a snapshot and split it across the ranks of `communicator`.
+///
+/// The snapshot may have been written with any number of ranks,
+/// as long as every current rank gets at least 2 rows.
+pub fn read_snapshot<const WIDTH: usize>(
+    path: &Path,
+    communicator: Option<SimpleCommunicator>,
+) -> Simulation<WIDTH>
+where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    let Some(communicator) = communicator else {
+        let bytes = std::fs::read(path).unwrap();
+        let header = read_header::<WIDTH>(bytes[..SnapshotHeader::SIZE].try_into().unwrap());
+        if bytes.len() as u64 != header.file_size() {
+            panic!("Snapshot {} is truncated", path.display());
+        }
+        let mut simulation = Simulation::new(header.height as usize, None);
+        for (row, bytes) in simulation
+            .grid_mut()
+            .iter_mut()
+            .zip(bytes[SnapshotHeader::SIZE..].chunks_exact(WIDTH))
+        {
+            for (cell, byte) in row.iter_mut().zip(bytes) {
+                cell.raw = *byte;
+            }
+        }
+        simulation.set_round(header.round as usize);
+        return simulation;
+    };
+
+    unsafe {
+        let mut file = open_file(&communicator, path, ffi::MPI_MODE_RDONLY);
+        let mut bytes = [0u8; SnapshotHeader::SIZE];
+        check(
+            ffi::MPI_File_read_at_all(
+                file,
+                0,
+                bytes.as_mut_ptr() as *mut c_void,
+                bytes.len() as c_int,
+                ffi::RSMPI_UINT8_T,
+                ffi::RSMPI_STATUS_IGNORE,
+            ),
+            "MPI_File_read_at_all",
+        );
+        let header = read_header::<WIDTH>(&bytes);
+        let mut file_size: ffi::MPI_Offset = 0;
+        check(
+            ffi::MPI_File_get_size(file, &mut file_size),
+            "MPI_File_get_size",
+        );
+        if file_size as u64 != header.file_size() {
+            panic!("Snapshot {} is truncated", path.display());
+        }
+        let longest = strip_of_rank(header.height as usize, 0, communicator.size() as usize).len();
+
+        let mut simulation = Simulation::<WIDTH>::new(header.height as usize, Some(communicator));
+        let row_offset = simulation.row_offset();
+        let grid = simulation.grid_mut();
+        for chunk in row_chunks::<WIDTH>(grid.len(), longest) {
+            let offset = SnapshotHeader::SIZE + (row_offset + chunk.start) * WIDTH;
+            check(
+                ffi::MPI_File_read_at_all(
+                    file,
+                    offset as ffi::MPI_Offset,
+                    grid[chunk.clone()].as_mut_ptr() as *mut c_void,
+                    (chunk.len() * WIDTH) as c_int,
+                    ffi::RSMPI_UINT8_T,
+                    ffi::RSMPI_STATUS_IGNORE,
+                ),
+                "MPI_File_read_at_all",
+            );
+        }
+        check(ffi::MPI_File_close(&mut file), "MPI_File_close");
+        simulation.set_round(header.round as usize);
+        simulation
+    }
+}
+
+fn read_header<const WIDTH: usize>(bytes: &[u8; SnapshotHeader::SIZE]) -> SnapshotHeader {
+    let header = SnapshotHeader::from_bytes(bytes).unwrap();
+    if header.width != WIDTH as u64 {
+        panic!(
+            "The snapshot has a width of {}, but this binary was compiled for {}",
+            header.width, WIDTH
+        );
+    }
+    header
+}
+
+/// Writes a snapshot every `interval` rounds into `snapshot_<round>.lgca`
+pub struct SnapshotWriter {
+    directory: PathBuf,
+    interval: usize,
+}
+
+impl SnapshotWriter {
+    pub fn new(directory: PathBuf, interval: usize) -> Self {
+        assert!(interval > 0, "The snapshot interval must be positive");
+        Self {
+            directory,
+            interval,
+        }
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for SnapshotWriter {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        if simulation.round() % self.interval == 0 {
+            let path = self
+                .directory
+                .join(format!("snapshot_{}.lgca", simulation.round()));
+            write_snapshot(simulation, &path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::testing::TempDir;
+
+    #[test]
+    fn header_round_trip() {
+        let header = SnapshotHeader {
+            width: 100,
+            height: 20,
+            round: 7,
+        };
+        let mut bytes = header.to_bytes();
+        assert_eq!(SnapshotHeader::from_bytes(&bytes), Ok(header));
+        bytes[8] = 2;
+        assert!(SnapshotHeader::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn shorter_strips_make_as_many_calls_as_the_longest() {
+        let chunks: Vec<_> = row_chunks::<{ c_int::MAX as usize / 4 }>(5, 7).collect();
+        assert_eq!(chunks, vec![0..4, 4..5]);
+        let chunks: Vec<_> = row_chunks::<{ c_int::MAX as usize / 4 }>(3, 7).collect();
+        assert_eq!(chunks, vec![0..3, 3..3]);
+    }
+
+    #[test]
+    fn snapshot_round_trip_without_mpi() {
+        let mut simulation = Simulation::<12>::new(7, None);
+        simulation.fill_box(4);
+        simulation.add_noise(0.2);
+        simulation.step();
+        let directory = TempDir::new("snapshot");
+        let path = directory.join("snapshot.lgca");
+
+        write_snapshot(&simulation, &path);
+        let restored = read_snapshot::<12>(&path, None);
+
+        assert_eq!(restored.grid(), simulation.grid());
+        assert_eq!(restored.round(), 1);
+    }
+}
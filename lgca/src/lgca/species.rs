@@ -0,0 +1,295 @@
+//! Two immiscible species of particles.
+//!
+//! The colors are a second grid of cells next to the particles. A set bit marks the particle in that channel as red,
+//! otherwise it is blue. Bits of empty channels are always zero, so the colors move through the same kernels.
+use rand::prelude::*;
+use rayon::prelude::*;
+
+use super::{
+    cell::{RNG, TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST, TO_WEST},
+    lattice::{FHP_EVEN_ROW_OFFSETS, FHP_ODD_ROW_OFFSETS},
+    Cell,
+};
+
+/// Directions with their vector in integer units.
+///
+/// x is measured in half cells and y in rows, so the scalar product of two
+/// vectors is `x_a * x_b + 3 * y_a * y_b` (times four).
+const DIRECTIONS: [(u8, (i32, i32)); 6] = [
+    (TO_EAST, (2, 0)),
+    (TO_NORTH_EAST, (1, -1)),
+    (TO_NORTH_WEST, (-1, -1)),
+    (TO_WEST, (-2, 0)),
+    (TO_SOUTH_WEST, (-1, 1)),
+    (TO_SOUTH_EAST, (1, 1)),
+];
+
+fn dot((x_a, y_a): (i32, i32), (x_b, y_b): (i32, i32)) -> i32 {
+    x_a * x_b + 3 * y_a * y_b
+}
+
+fn momentum(raw: u8) -> (i32, i32) {
+    DIRECTIONS
+        .iter()
+        .filter(|(direction, _)| raw & direction != 0)
+        .fold((0, 0), |(x, y), (_, (dx, dy))| (x + dx, y + dy))
+}
+
+/// Red particles count positive, blue particles negative
+fn color_flux(raw: u8, red: u8) -> (i32, i32) {
+    DIRECTIONS
+        .iter()
+        .filter(|(direction, _)| raw & direction != 0)
+        .fold((0, 0), |(x, y), (direction, (dx, dy))| {
+            let sign = if red & direction != 0 { 1 } else { -1 };
+            (x + sign * dx, y + sign * dy)
+        })
+}
+
+/// Moved cells and colors of a row
+type Row<'a, const WIDTH: usize> = (&'a [Cell; WIDTH], &'a [Cell; WIDTH]);
+
+/// Points towards the neighbors of cell `x` with more red than blue particles.
+///
+/// `rows` holds the row above, the row of the cell and the row below, `None` outside of the grid.
+fn color_field<const WIDTH: usize>(
+    x: usize,
+    even: bool,
+    rows: [Option<Row<WIDTH>>; 3],
+    periodic_columns: bool,
+) -> (i32, i32) {
+    let offsets = if even {
+        FHP_EVEN_ROW_OFFSETS
+    } else {
+        FHP_ODD_ROW_OFFSETS
+    };
+    offsets.iter().zip(DIRECTIONS.iter()).fold(
+        (0, 0),
+        |(x_field, y_field), ((offset_x, offset_y), (_, (dx, dy)))| {
+            let mut neighbor_x = x as isize + offset_x;
+            if periodic_columns {
+                neighbor_x = neighbor_x.rem_euclid(WIDTH as isize);
+            }
+            match rows[(1 + offset_y) as usize] {
+                Some((cells, colors)) if (0..WIDTH as isize).contains(&neighbor_x) => {
+                    let red = colors[neighbor_x as usize].get_particles() as i32;
+                    let blue = cells[neighbor_x as usize].get_particles() as i32 - red;
+                    (x_field + (red - blue) * dx, y_field + (red - blue) * dy)
+                }
+                _ => (x_field, y_field),
+            }
+        },
+    )
+}
+
+/// Rothman–Keller collision for two immiscible species.
+///
+/// Chooses the configuration with the same particles, red particles and momentum,
+/// that sends the most red particles towards the red neighbors. Ties are broken randomly.
+pub fn process_immiscible_collision(
+    cell: &mut Cell,
+    colors: &mut Cell,
+    field: (i32, i32),
+    random: &mut impl Rng,
+) {
+    let particles = cell.get_particles();
+    let red_particles = colors.get_particles();
+    let cell_momentum = momentum(cell.raw);
+
+    let mut best = (cell.raw, colors.raw);
+    let mut best_work = i32::MIN;
+    let mut ties = 0;
+    for candidate in 0..64u8 {
+        if candidate.count_ones() as u8 != particles || momentum(candidate) != cell_momentum {
+            continue;
+        }
+        // Visit every subset of the candidate as coloring
+        let mut red = candidate;
+        loop {
+            if red.count_ones() as u8 == red_particles {
+                let work = dot(color_flux(candidate, red), field);
+                if work > best_work {
+                    best = (candidate, red);
+                    best_work = work;
+                    ties = 1;
+                } else if work == best_work {
+                    ties += 1;
+                    if random.gen_range(0..ties) == 0 {
+                        best = (candidate, red);
+                    }
+                }
+            }
+            if red == 0 {
+                break;
+            }
+            red = (red - 1) & candidate;
+        }
+    }
+
+    cell.raw = best.0;
+    colors.raw = best.1;
+}
+
+/// Collide the moved cells of a strip with the rules of Rothman and Keller.
+///
+/// `above` and `below` are the moved cells and colors of the rows around the strip, `None` at the top and bottom of
+/// the grid. Neighbors beyond the west and east edge do not count for the color field, unless the columns are periodic.
+pub fn collide_species<const WIDTH: usize>(
+    cells: &mut [[Cell; WIDTH]],
+    colors: &mut [[Cell; WIDTH]],
+    above: Option<Row<WIDTH>>,
+    below: Option<Row<WIDTH>>,
+    row_offset: usize,
+    periodic_columns: bool,
+) {
+    // The color field needs the moved state of the neighbors, so it is calculated before any cell collides
+    let (moved_cells, moved_colors): (&[[Cell; WIDTH]], &[[Cell; WIDTH]]) = (cells, colors);
+    let height = moved_cells.len();
+    let row = |y: isize| match y {
+        -1 => above,
+        y if y as usize == height => below,
+        y => Some((&moved_cells[y as usize], &moved_colors[y as usize])),
+    };
+    let fields: Vec<[(i32, i32); WIDTH]> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            let rows = [row(y as isize - 1), row(y as isize), row(y as isize + 1)];
+            let even = (row_offset + y) % 2 == 0;
+            std::array::from_fn(|x| color_field(x, even, rows, periodic_columns))
+        })
+        .collect();
+
+    cells
+        .par_iter_mut()
+        .zip(colors.par_iter_mut())
+        .zip(fields.par_iter())
+        .for_each(|((cell_row, color_row), field_row)| {
+            RNG.with(|random| {
+                let random = &mut *random.borrow_mut();
+                for x in 0..WIDTH {
+                    process_immiscible_collision(
+                        &mut cell_row[x],
+                        &mut color_row[x],
+                        field_row[x],
+                        random,
+                    );
+                }
+            })
+        });
+}
+
+/// How well the species are separated.
+///
+/// 0 for a perfect mixture in every cell, 1 if no cell contains both species.
+pub fn segregation<const WIDTH: usize>(cells: &[[Cell; WIDTH]], colors: &[[Cell; WIDTH]]) -> f64 {
+    let (difference, total) = cells.iter().flatten().zip(colors.iter().flatten()).fold(
+        (0, 0),
+        |(difference, total), (cell, color)| {
+            let red = color.get_particles() as i64;
+            let blue = cell.get_particles() as i64 - red;
+            (difference + (red - blue).abs(), total + red + blue)
+        },
+    );
+    difference as f64 / total.max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{
+        boundary::{Edge, EdgeBoundary},
+        simulation::Simulation,
+    };
+
+    #[test]
+    fn collision_keeps_particles_colors_and_momentum() {
+        let random = &mut SmallRng::seed_from_u64(0);
+        for raw in 0..64u8 {
+            let mut red = raw;
+            loop {
+                let mut cell = Cell { raw };
+                let mut colors = Cell { raw: red };
+                process_immiscible_collision(&mut cell, &mut colors, (3, -1), random);
+
+                assert_eq!(cell.get_particles(), raw.count_ones() as u8);
+                assert_eq!(colors.get_particles(), red.count_ones() as u8);
+                assert_eq!(colors.raw & !cell.raw, 0);
+                assert_eq!(momentum(cell.raw), momentum(raw));
+
+                if red == 0 {
+                    break;
+                }
+                red = (red - 1) & raw;
+            }
+        }
+    }
+
+    #[test]
+    fn collision_sends_red_particles_towards_red() {
+        let random = &mut SmallRng::seed_from_u64(0);
+        let mut cell = Cell {
+            raw: TO_EAST | TO_WEST,
+        };
+        let mut colors = Cell { raw: TO_WEST };
+        process_immiscible_collision(&mut cell, &mut colors, (2, 0), random);
+        assert_eq!(colors.raw, TO_EAST);
+    }
+
+    #[test]
+    fn mixture_separates() {
+        let mut simulation = Simulation::<32>::new(32, None);
+        simulation.set_boundary(Box::new(EdgeBoundary::new(
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+        )));
+        simulation.add_seeded_noise(0.4, 1);
+        simulation.set_species(0.5, 1);
+        let particles = simulation.particles();
+        let segregation_of = |simulation: &Simulation<32>| {
+            segregation(simulation.grid(), simulation.colors().unwrap())
+        };
+
+        let initial = segregation_of(&simulation);
+        simulation.run(50, &mut []);
+        assert!(segregation_of(&simulation) > initial);
+        assert_eq!(simulation.particles(), particles);
+        let colors = simulation.colors().unwrap();
+        for (cell, color) in simulation
+            .grid()
+            .iter()
+            .flatten()
+            .zip(colors.iter().flatten())
+        {
+            assert_eq!(color.raw & !cell.raw, 0);
+        }
+    }
+
+    #[test]
+    fn colors_follow_the_particles_between_walls() {
+        let mut simulation = Simulation::<16>::new(11, None);
+        simulation.add_seeded_noise(0.3, 2);
+        simulation.set_species(0.5, 2);
+        let red = |simulation: &Simulation<16>| -> u64 {
+            let colors = simulation.colors().unwrap();
+            colors
+                .iter()
+                .flatten()
+                .map(|cell| cell.get_particles() as u64)
+                .sum()
+        };
+        let red_particles = red(&simulation);
+        simulation.run(30, &mut []);
+        assert_eq!(red(&simulation), red_particles);
+        let colors = simulation.colors().unwrap();
+        for (cell, color) in simulation
+            .grid()
+            .iter()
+            .flatten()
+            .zip(colors.iter().flatten())
+        {
+            assert_eq!(color.raw & !cell.raw, 0);
+        }
+    }
+}
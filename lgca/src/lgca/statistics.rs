@@ -0,0 +1,186 @@
+//! Per round statistics of cell states and collisions.
+//!
+//! Every collision rule, random, fake or chiral, maps the states of a collision class onto the same class.
+//! So the number of cells in the states of a class after a round is the number of cells that hit this class
+//! in the round, and all counts can be taken from a histogram of the states after the round.
+use rayon::prelude::*;
+use std::{io::Write, path::PathBuf};
+
+use super::{
+    cell::{TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST, TO_WEST},
+    output::{Output, RootCsv},
+    simulation::Simulation,
+    Cell,
+};
+
+/// Two particles colliding head on
+pub const TWO_BODY: [u8; 3] = [
+    TO_WEST | TO_EAST,
+    TO_NORTH_WEST | TO_SOUTH_EAST,
+    TO_NORTH_EAST | TO_SOUTH_WEST,
+];
+/// Three particles 120 degrees apart
+pub const THREE_BODY: [u8; 2] = [
+    TO_WEST | TO_NORTH_EAST | TO_SOUTH_EAST,
+    TO_EAST | TO_NORTH_WEST | TO_SOUTH_WEST,
+];
+/// Four particles with two opposing holes
+pub const FOUR_BODY: [u8; 3] = [
+    0b00111111 ^ TWO_BODY[0],
+    0b00111111 ^ TWO_BODY[1],
+    0b00111111 ^ TWO_BODY[2],
+];
+
+/// Number of cells in each of the 64 states
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StateHistogram {
+    pub counts: [u64; 64],
+}
+
+impl StateHistogram {
+    pub fn from_grid<const WIDTH: usize>(grid: &[[Cell; WIDTH]]) -> Self {
+        let counts = grid
+            .par_iter()
+            .fold(
+                || [0u64; 64],
+                |mut counts, row| {
+                    for cell in row {
+                        counts[(cell.raw & 0b00111111) as usize] += 1;
+                    }
+                    counts
+                },
+            )
+            .reduce(
+                || [0u64; 64],
+                |mut counts, other| {
+                    for (count, other) in counts.iter_mut().zip(other) {
+                        *count += other;
+                    }
+                    counts
+                },
+            );
+        Self { counts }
+    }
+
+    fn class(&self, states: &[u8]) -> u64 {
+        states
+            .iter()
+            .map(|state| self.counts[*state as usize])
+            .sum()
+    }
+
+    pub fn two_body(&self) -> u64 {
+        self.class(&TWO_BODY)
+    }
+
+    pub fn three_body(&self) -> u64 {
+        self.class(&THREE_BODY)
+    }
+
+    pub fn four_body(&self) -> u64 {
+        self.class(&FOUR_BODY)
+    }
+}
+
+/// Writes the collision counts and the state histogram of the whole grid after every round into `collisions.csv`.
+///
+/// The histograms of all ranks are summed up, only the first rank writes. Body forces change cells after the
+/// collisions, so the counts are only exact without them.
+pub struct CollisionStatistics {
+    csv: RootCsv,
+    /// Histogram of the whole grid in the last observed round
+    pub last: Option<StateHistogram>,
+}
+
+impl CollisionStatistics {
+    pub fn new(path: PathBuf) -> Self {
+        let states: String = (0..64).map(|state| format!(",state_{}", state)).collect();
+        Self {
+            csv: RootCsv::new(
+                path,
+                format!("round,two_body,three_body,four_body{}", states),
+            ),
+            last: None,
+        }
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for CollisionStatistics {
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        let local = StateHistogram::from_grid(simulation.grid());
+        let histogram = StateHistogram {
+            counts: simulation.sum_over_ranks(&local.counts).try_into().unwrap(),
+        };
+        self.last = Some(histogram);
+
+        let Some(file) = self.csv.file(simulation.rank()) else {
+            return;
+        };
+        write!(
+            file,
+            "{},{},{},{}",
+            simulation.round(),
+            histogram.two_body(),
+            histogram.three_body(),
+            histogram.four_body()
+        )
+        .unwrap();
+        for count in histogram.counts {
+            write!(file, ",{}", count).unwrap();
+        }
+        writeln!(file).unwrap();
+    }
+
+    fn finish(&mut self) {
+        self.csv.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::testing::TempDir;
+
+    fn class_of(state: u8) -> usize {
+        [&TWO_BODY[..], &THREE_BODY[..], &FOUR_BODY[..]]
+            .iter()
+            .position(|class| class.contains(&state))
+            .unwrap_or(3)
+    }
+
+    #[test]
+    fn collisions_stay_in_their_class() {
+        for raw in 0..64u8 {
+            for _ in 0..8 {
+                let mut cell = Cell { raw };
+                cell.process_collision();
+                assert_eq!(class_of(cell.raw), class_of(raw));
+            }
+            for neighbors in 0..8u8 {
+                let neighbor = |bit: u8| Cell {
+                    raw: if neighbors & bit != 0 { 0b00111111 } else { 0 },
+                };
+                let mut cell = Cell { raw };
+                cell.process_fake_collision(&neighbor(1), &neighbor(2), &neighbor(4));
+                assert_eq!(class_of(cell.raw), class_of(raw));
+            }
+        }
+    }
+
+    #[test]
+    fn histogram_counts_every_cell() {
+        let mut simulation = Simulation::<12>::new(10, None);
+        simulation.add_seeded_noise(0.5, 2);
+        let directory = TempDir::new("collisions");
+        let mut statistics = CollisionStatistics::new(directory.join("collisions.csv"));
+        simulation.run(3, &mut [&mut statistics]);
+        Output::<12>::finish(&mut statistics);
+
+        let histogram = statistics.last.unwrap();
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 12 * 10);
+        assert_eq!(histogram, StateHistogram::from_grid(simulation.grid()));
+        assert!(histogram.two_body() + histogram.three_body() + histogram.four_body() > 0);
+        let lines = std::fs::read_to_string(&statistics.csv.path).unwrap();
+        assert_eq!(lines.lines().count(), 5);
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use super::{simulation::Simulation, visualization::cells_to_color, Cell};
+
+/// How cells are drawn into the terminal
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Glyphs {
+    /// Two pixels per character, the upper one as foreground and the lower one as background color
+    HalfBlocks,
+    /// Eight pixels per character as braille dots. A dot is set if its cells contain particles
+    Braille,
+}
+
+impl Glyphs {
+    /// Number of pixels per character horizontally and vertically
+    fn pixels_per_character(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlocks => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+}
+
+/// The part of the grid that is visible
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    /// First visible column
+    pub x: usize,
+    /// First visible row
+    pub y: usize,
+    /// Number of cells per pixel in each direction
+    pub zoom: usize,
+    /// Size of the drawing area in characters
+    pub columns: usize,
+    pub lines: usize,
+}
+
+/// Cells covered by the pixel at `pixel_x`, `pixel_y`. Empty if the pixel is outside of the grid
+fn cells_of_pixel<'a, const WIDTH: usize>(
+    grid: &'a [[Cell; WIDTH]],
+    viewport: &Viewport,
+    pixel_x: usize,
+    pixel_y: usize,
+) -> Vec<&'a Cell> {
+    let x = viewport.x + pixel_x * viewport.zoom;
+    let y = viewport.y + pixel_y * viewport.zoom;
+    grid.iter()
+        .skip(y)
+        .take(viewport.zoom)
+        .flat_map(|row| row.iter().skip(x).take(viewport.zoom))
+        .collect()
+}
+
+/// Draw the visible part of the grid with ANSI colors. Every line ends with a reset and a newline
+pub fn render_frame<const WIDTH: usize>(
+    grid: &[[Cell; WIDTH]],
+    viewport: &Viewport,
+    glyphs: Glyphs,
+) -> String {
+    let (pixels_x, pixels_y) = glyphs.pixels_per_character();
+    let mut frame = String::new();
+    for line in 0..viewport.lines {
+        for column in 0..viewport.columns {
+            match glyphs {
+                Glyphs::HalfBlocks => {
+                    let upper = cells_of_pixel(grid, viewport, column, line * 2);
+                    let lower = cells_of_pixel(grid, viewport, column, line * 2 + 1);
+                    if upper.is_empty() {
+                        frame.push_str("\x1b[0m ");
+                        continue;
+                    }
+                    let upper = cells_to_color(&upper);
+                    write!(frame, "\x1b[38;2;{};{};{}m", upper.r, upper.g, upper.b).unwrap();
+                    if lower.is_empty() {
+                        frame.push_str("\x1b[49m");
+                    } else {
+                        let lower = cells_to_color(&lower);
+                        write!(frame, "\x1b[48;2;{};{};{}m", lower.r, lower.g, lower.b).unwrap();
+                    }
+                    frame.push('▀');
+                }
+                Glyphs::Braille => {
+                    let mut block = Vec::new();
+                    let mut dots = 0u32;
+                    for dot_y in 0..pixels_y {
+                        for dot_x in 0..pixels_x {
+                            let cells = cells_of_pixel(
+                                grid,
+                                viewport,
+                                column * pixels_x + dot_x,
+                                line * pixels_y + dot_y,
+                            );
+                            if cells.iter().any(|cell| cell.get_particles() > 0) {
+                                dots |= braille_dot(dot_x, dot_y);
+                            }
+                            block.extend(cells);
+                        }
+                    }
+                    if dots == 0 {
+                        frame.push_str("\x1b[0m ");
+                        continue;
+                    }
+                    let color = cells_to_color(&block);
+                    write!(frame, "\x1b[0;38;2;{};{};{}m", color.r, color.g, color.b).unwrap();
+                    frame.push(char::from_u32(0x2800 + dots).unwrap());
+                }
+            }
+        }
+        frame.push_str("\x1b[0m\r\n");
+    }
+    frame
+}
+
+/// Bit of a dot in a braille character
+fn braille_dot(x: usize, y: usize) -> u32 {
+    match (x, y) {
+        (0, 3) => 0x40,
+        (1, 3) => 0x80,
+        (0, y) => 1 << y,
+        (_, y) => 1 << (y + 3),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Key {
+    Pause,
+    Step,
+    Faster,
+    Slower,
+    ZoomIn,
+    ZoomOut,
+    Up,
+    Down,
+    Left,
+    Right,
+    ToggleGlyphs,
+    Quit,
+}
+
+/// Translate raw terminal input into keys. Unknown input is ignored
+pub fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut index = 0;
+    while index < input.len() {
+        // Arrow keys are sent as ESC [ A..D
+        if input[index..].starts_with(b"\x1b[") && index + 2 < input.len() {
+            match input[index + 2] {
+                b'A' => keys.push(Key::Up),
+                b'B' => keys.push(Key::Down),
+                b'C' => keys.push(Key::Right),
+                b'D' => keys.push(Key::Left),
+                _ => {}
+            }
+            index += 3;
+            continue;
+        }
+        match input[index] {
+            b' ' | b'p' => keys.push(Key::Pause),
+            b'n' | b'.' => keys.push(Key::Step),
+            b'+' | b'=' => keys.push(Key::Faster),
+            b'-' => keys.push(Key::Slower),
+            b'z' => keys.push(Key::ZoomIn),
+            b'x' => keys.push(Key::ZoomOut),
+            b'k' => keys.push(Key::Up),
+            b'j' => keys.push(Key::Down),
+            b'h' => keys.push(Key::Left),
+            b'l' => keys.push(Key::Right),
+            b'b' => keys.push(Key::ToggleGlyphs),
+            b'q' | 3 => keys.push(Key::Quit),
+            _ => {}
+        }
+        index += 1;
+    }
+    keys
+}
+
+/// Puts the terminal into raw mode and an alternate screen until it is dropped
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> Self {
+        unsafe {
+            let mut original = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                panic!("The interactive mode needs a terminal");
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            // Reads return immediately, even without input
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            print!("\x1b[?1049h\x1b[?25l");
+            Self { original }
+        }
+    }
+
+    /// Size of the terminal in characters, falls back to 80x24
+    fn size(&self) -> (usize, usize) {
+        unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0
+                || size.ws_col == 0
+            {
+                return (80, 24);
+            }
+            (size.ws_col as usize, size.ws_row as usize)
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        std::io::stdout().flush().unwrap();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Interactive view of a small simulation in the terminal.
+///
+/// Space pauses, n steps a single round, +/- change the speed, z/x zoom, arrows or hjkl move,
+/// b switches between half blocks and braille and q quits.
+pub struct TerminalViewer {
+    /// Rounds per second
+    pub speed: f64,
+    pub frames_per_second: f64,
+    pub glyphs: Glyphs,
+    pub paused: bool,
+    x: usize,
+    y: usize,
+    zoom: usize,
+}
+
+impl TerminalViewer {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            speed,
+            frames_per_second: 30.0,
+            glyphs: Glyphs::HalfBlocks,
+            paused: false,
+            x: 0,
+            y: 0,
+            zoom: 1,
+        }
+    }
+
+    fn handle_key<const WIDTH: usize>(&mut self, key: Key, simulation: &Simulation<WIDTH>) {
+        let step = 8 * self.zoom;
+        match key {
+            Key::Pause => self.paused = !self.paused,
+            Key::Faster => self.speed *= 2.0,
+            Key::Slower => self.speed = (self.speed / 2.0).max(0.25),
+            Key::ZoomIn => self.zoom = (self.zoom / 2).max(1),
+            Key::ZoomOut => self.zoom = (self.zoom * 2).min(WIDTH.max(simulation.height())),
+            Key::Up => self.y = self.y.saturating_sub(step),
+            Key::Down => self.y = (self.y + step).min(simulation.height().saturating_sub(1)),
+            Key::Left => self.x = self.x.saturating_sub(step),
+            Key::Right => self.x = (self.x + step).min(WIDTH - 1),
+            Key::ToggleGlyphs => {
+                self.glyphs = match self.glyphs {
+                    Glyphs::HalfBlocks => Glyphs::Braille,
+                    Glyphs::Braille => Glyphs::HalfBlocks,
+                }
+            }
+            Key::Step | Key::Quit => {}
+        }
+    }
+
+    fn draw<const WIDTH: usize>(
+        &self,
+        simulation: &Simulation<WIDTH>,
+        terminal: &RawTerminal,
+        rounds: usize,
+    ) {
+        let (columns, lines) = terminal.size();
+        let viewport = Viewport {
+            x: self.x,
+            y: self.y,
+            zoom: self.zoom,
+            columns,
+            lines: lines.saturating_sub(1),
+        };
+        let mut screen = String::from("\x1b[H");
+        screen.push_str(&render_frame(simulation.grid(), &viewport, self.glyphs));
+        let status = format!(
+            "round {}/{} | {} rounds/s | zoom {} | ({}, {}){} | space pause, n step, +/- speed, z/x zoom, arrows move, b glyphs, q quit",
+            simulation.round(),
+            rounds,
+            self.speed,
+            self.zoom,
+            self.x,
+            self.y,
+            if self.paused { " | paused" } else { "" },
+        );
+        screen.extend(status.chars().take(columns));
+        screen.push_str("\x1b[K");
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(screen.as_bytes()).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    /// Show the simulation and calculate up to `rounds` rounds until q is pressed
+    pub fn run<const WIDTH: usize>(&mut self, simulation: &mut Simulation<WIDTH>, rounds: usize)
+    where
+        [(); WIDTH - 1]:,
+        [(); WIDTH - 2]:,
+    {
+        let terminal = RawTerminal::enable();
+        let mut stdin = std::io::stdin();
+        let mut input = [0u8; 64];
+        let mut owed_rounds = 0.0;
+        let mut last_frame = Instant::now();
+
+        loop {
+            let length = stdin.read(&mut input).unwrap_or(0);
+            let mut single_step = false;
+            for key in parse_keys(&input[..length]) {
+                match key {
+                    Key::Quit => return,
+                    Key::Step => {
+                        self.paused = true;
+                        single_step = true;
+                    }
+                    key => self.handle_key(key, simulation),
+                }
+            }
+
+            let elapsed = last_frame.elapsed();
+            last_frame = Instant::now();
+            if !self.paused {
+                owed_rounds += elapsed.as_secs_f64() * self.speed;
+            }
+            let mut due = if single_step { 1 } else { owed_rounds as usize };
+            owed_rounds -= owed_rounds.floor();
+            due = due.min(rounds.saturating_sub(simulation.round()));
+            for _ in 0..due {
+                simulation.step();
+            }
+
+            self.draw(simulation, &terminal, rounds);
+            std::thread::sleep(
+                Duration::from_secs_f64(1.0 / self.frames_per_second)
+                    .saturating_sub(last_frame.elapsed()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn braille_dots_follow_the_unicode_layout() {
+        let mut grid = [[Cell::new(); 2]; 4];
+        grid[0][0].set_to_east(true);
+        grid[3][1].set_to_east(true);
+        let viewport = Viewport {
+            x: 0,
+            y: 0,
+            zoom: 1,
+            columns: 1,
+            lines: 1,
+        };
+        let frame = render_frame(&grid, &viewport, Glyphs::Braille);
+        assert!(frame.contains('\u{2881}'), "{}", frame);
+
+        let frame = render_frame(&grid, &viewport, Glyphs::HalfBlocks);
+        assert_eq!(frame.matches('▀').count(), 1);
+    }
+
+    #[test]
+    fn parse_arrows_and_letters() {
+        assert_eq!(
+            parse_keys(b"\x1b[A \x1b[Dnq"),
+            vec![Key::Up, Key::Pause, Key::Left, Key::Step, Key::Quit]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Helpers that are shared by the tests of several modules
+use std::path::{Path, PathBuf};
+
+/// A fresh directory in the temp directory that is removed with its contents when it is dropped,
+/// so the files are cleaned up even if the test fails
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// `name` has to be unique among the tests, because they run in parallel in the same process
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("lgca_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of a file in the directory
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
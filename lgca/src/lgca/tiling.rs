@@ -0,0 +1,335 @@
+//! Cache blocking in space and time.
+//!
+//! Every round of `Simulation::step` streams both buffers through memory once, so wide grids are memory bound.
+//! With temporal blocking the rows are split into tiles that fit into the cache,
+//! and every tile advances several rounds before the next one is touched.
+//!
+//! First every tile calculates a trapezoid that shrinks by one row per round towards its neighboring tiles,
+//! all tiles in parallel. Afterwards the gaps between the trapezoids are filled round by round, also in parallel.
+//! Every row is calculated exactly once per round, so the result is the same as with single rounds.
+//! Two buffers are enough, because a row is only overwritten after every row that depends on it was calculated.
+use rayon::prelude::*;
+use std::ops::Range;
+
+use super::Cell;
+
+/// Size of the tiles and how many rounds they advance at once
+#[derive(Copy, Clone, Debug)]
+pub struct TemporalBlocking {
+    /// Rows per tile. The last tile also gets the remaining rows
+    pub tile_height: usize,
+    /// Rounds every tile advances before the gaps between the tiles are filled
+    pub depth: usize,
+}
+
+impl TemporalBlocking {
+    pub fn new(tile_height: usize, depth: usize) -> Self {
+        assert!(
+            depth > 0,
+            "The depth of the temporal blocking must be positive"
+        );
+        assert!(
+            tile_height >= 2 * depth,
+            "Tiles need at least twice as many rows as rounds they advance at once"
+        );
+        Self { tile_height, depth }
+    }
+
+    /// Rows of every tile
+    pub fn tiles(&self, height: usize) -> Vec<Range<usize>> {
+        let count = (height / self.tile_height).max(1);
+        (0..count)
+            .map(|index| {
+                let end = if index + 1 == count {
+                    height
+                } else {
+                    (index + 1) * self.tile_height
+                };
+                index * self.tile_height..end
+            })
+            .collect()
+    }
+
+    /// Advance the rows in `buffers[0]` by `rounds` rounds, at most `depth`.
+    ///
+    /// Afterwards the rows are in `buffers[rounds % 2]`.
+    /// `movement` calculates a row of the next round from the index of the row and the rows around it.
+    /// The row above or below is `None` at the top and bottom of the whole grid.
+    ///
+    /// `receive` holds the rows of the previous and next rank, if there are any. Then `exchange` is called
+    /// once per round with the first and last row and has to receive the border rows of the neighbors into `receive`.
+    pub fn advance<const WIDTH: usize>(
+        &self,
+        buffers: [&mut [[Cell; WIDTH]]; 2],
+        mut receive: [Option<&mut [Cell; WIDTH]>; 2],
+        rounds: usize,
+        movement: impl Fn(
+                usize,
+                Option<&[Cell; WIDTH]>,
+                &[Cell; WIDTH],
+                Option<&[Cell; WIDTH]>,
+                &mut [Cell; WIDTH],
+            ) + Sync,
+        mut exchange: impl FnMut(&[Cell; WIDTH], &[Cell; WIDTH], &mut [Option<&mut [Cell; WIDTH]>; 2]),
+    ) {
+        let [current, next] = buffers;
+        let height = current.len();
+        assert!(
+            rounds <= self.depth && 2 * rounds <= height,
+            "Can not advance {} rows by {} rounds at once",
+            height,
+            rounds
+        );
+        if rounds == 0 {
+            return;
+        }
+        let has_neighbors = receive.iter().any(Option::is_some);
+        if has_neighbors {
+            exchange(&current[0], &current[height - 1], &mut receive);
+        }
+
+        // The trapezoids only read rows of other tiles in the first round, before anything is overwritten
+        let tiles = self.tiles(height);
+        let mut halos = Vec::with_capacity(2 * (tiles.len() - 1));
+        for tile in &tiles[1..] {
+            halos.extend_from_slice(&current[tile.start - 1..tile.start + 1]);
+        }
+        let last_tile = tiles.len() - 1;
+        split_ranges(current, &tiles)
+            .into_par_iter()
+            .zip(split_ranges(next, &tiles))
+            .zip(tiles.par_iter())
+            .enumerate()
+            .for_each(|(index, ((current, next), rows))| {
+                let top = if index == 0 {
+                    receive[0].as_deref()
+                } else {
+                    Some(&halos[2 * index - 2])
+                };
+                let bottom = if index == last_tile {
+                    receive[1].as_deref()
+                } else {
+                    Some(&halos[2 * index + 1])
+                };
+                advance_trapezoid(
+                    [current, next],
+                    rows.start,
+                    [top, bottom],
+                    rounds,
+                    &movement,
+                );
+            });
+
+        // Fill the gaps around every border between tiles or ranks
+        let mut borders: Vec<usize> = tiles[1..].iter().map(|tile| tile.start).collect();
+        if receive[0].is_some() {
+            borders.insert(0, 0);
+        }
+        if receive[1].is_some() {
+            borders.push(height);
+        }
+        let gaps: Vec<Range<usize>> = borders
+            .iter()
+            .map(|border| border.saturating_sub(rounds)..(border + rounds).min(height))
+            .collect();
+        for round in 2..=rounds {
+            let (read, write) = if round % 2 == 1 {
+                (&*current, &mut *next)
+            } else {
+                (&*next, &mut *current)
+            };
+            if has_neighbors {
+                exchange(&read[0], &read[height - 1], &mut receive);
+            }
+            split_ranges(write, &gaps)
+                .into_par_iter()
+                .zip(gaps.par_iter().zip(borders.par_iter()))
+                .for_each(|(write, (gap, border))| {
+                    let rows = border.saturating_sub(round - 1)..(border + round - 1).min(height);
+                    for y in rows {
+                        let above = if y == 0 {
+                            receive[0].as_deref()
+                        } else {
+                            Some(&read[y - 1])
+                        };
+                        let below = if y + 1 == height {
+                            receive[1].as_deref()
+                        } else {
+                            Some(&read[y + 1])
+                        };
+                        movement(y, above, &read[y], below, &mut write[y - gap.start]);
+                    }
+                });
+        }
+    }
+}
+
+/// Advance a tile starting at row `offset` as far as possible without the rows of the neighboring tiles.
+///
+/// `halos` are the rows above and below the tile before the first round, `None` at the borders of the grid.
+fn advance_trapezoid<const WIDTH: usize>(
+    [current, next]: [&mut [[Cell; WIDTH]]; 2],
+    offset: usize,
+    [top, bottom]: [Option<&[Cell; WIDTH]>; 2],
+    rounds: usize,
+    movement: &(impl Fn(
+        usize,
+        Option<&[Cell; WIDTH]>,
+        &[Cell; WIDTH],
+        Option<&[Cell; WIDTH]>,
+        &mut [Cell; WIDTH],
+    ) + Sync),
+) {
+    let height = current.len();
+    for round in 1..=rounds {
+        let (read, write) = if round % 2 == 1 {
+            (&*current, &mut *next)
+        } else {
+            (&*next, &mut *current)
+        };
+        let first = if top.is_some() { round - 1 } else { 0 };
+        let last = if bottom.is_some() {
+            height - (round - 1)
+        } else {
+            height
+        };
+        for y in first..last {
+            let above = if y == 0 { top } else { Some(&read[y - 1]) };
+            let below = if y + 1 == height {
+                bottom
+            } else {
+                Some(&read[y + 1])
+            };
+            movement(offset + y, above, &read[y], below, &mut write[y]);
+        }
+    }
+}
+
+/// Split `slice` into mutable parts for sorted, disjoint `ranges`
+fn split_ranges<'a, T>(mut slice: &'a mut [T], ranges: &[Range<usize>]) -> Vec<&'a mut [T]> {
+    let mut parts = Vec::with_capacity(ranges.len());
+    let mut offset = 0;
+    for range in ranges {
+        let (_, rest) = std::mem::take(&mut slice).split_at_mut(range.start - offset);
+        let (part, rest) = rest.split_at_mut(range.len());
+        parts.push(part);
+        slice = rest;
+        offset = range.end;
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    const WIDTH: usize = 3;
+
+    /// Deterministic rule that depends on the position and on all neighbors
+    fn mix(
+        y: usize,
+        above: Option<&[Cell; WIDTH]>,
+        current: &[Cell; WIDTH],
+        below: Option<&[Cell; WIDTH]>,
+        result: &mut [Cell; WIDTH],
+    ) {
+        for x in 0..WIDTH {
+            let above = above.map_or(17, |row| row[x].raw);
+            let below = below.map_or(29, |row| row[(x + 1) % WIDTH].raw);
+            result[x].raw = above.rotate_left(1)
+                ^ below.wrapping_mul(3)
+                ^ current[x].raw.wrapping_add(y as u8)
+                ^ current[(x + 2) % WIDTH].raw;
+        }
+    }
+
+    fn initial_grid(height: usize) -> Vec<[Cell; WIDTH]> {
+        (0..height)
+            .map(|y| {
+                std::array::from_fn(|x| Cell {
+                    raw: (y * 7 + x * 31) as u8,
+                })
+            })
+            .collect()
+    }
+
+    fn reference(mut grid: Vec<[Cell; WIDTH]>, rounds: usize) -> Vec<[Cell; WIDTH]> {
+        let height = grid.len();
+        for _ in 0..rounds {
+            let mut next = grid.clone();
+            for y in 0..height {
+                let above = y.checked_sub(1).map(|y| &grid[y]);
+                mix(y, above, &grid[y], grid.get(y + 1), &mut next[y]);
+            }
+            grid = next;
+        }
+        grid
+    }
+
+    #[test]
+    fn same_result_as_single_rounds() {
+        let blocking = TemporalBlocking::new(6, 3);
+        for (height, rounds) in [(25, 3), (25, 2), (7, 3), (13, 1)] {
+            let mut current = initial_grid(height);
+            let mut next = current.clone();
+            blocking.advance(
+                [&mut current, &mut next],
+                [None, None],
+                rounds,
+                mix,
+                |_, _, _| unreachable!(),
+            );
+            let result = if rounds % 2 == 0 { current } else { next };
+            assert_eq!(result, reference(initial_grid(height), rounds));
+        }
+    }
+
+    #[test]
+    fn same_result_when_split_across_ranks() {
+        let (height, split, rounds) = (41, 19, 4);
+        let blocking = TemporalBlocking::new(8, 4);
+        let grid = initial_grid(height);
+        let (to_second, from_first) = channel();
+        let (to_first, from_second) = channel();
+
+        let mut upper = grid[..split].to_vec();
+        let mut lower = grid[split..].to_vec();
+        let (upper_rows, lower_rows) = (&mut upper, &mut lower);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                let mut next = upper_rows.clone();
+                let mut receive_bottom = [Cell::new(); WIDTH];
+                blocking.advance(
+                    [upper_rows, &mut next],
+                    [None, Some(&mut receive_bottom)],
+                    rounds,
+                    mix,
+                    |_, last, receive| {
+                        to_second.send(*last).unwrap();
+                        *receive[1].as_deref_mut().unwrap() = from_second.recv().unwrap();
+                    },
+                );
+            });
+            scope.spawn(move || {
+                let mut next = lower_rows.clone();
+                let mut receive_top = [Cell::new(); WIDTH];
+                blocking.advance(
+                    [lower_rows, &mut next],
+                    [Some(&mut receive_top), None],
+                    rounds,
+                    |y, above, current, below, result| {
+                        mix(y + split, above, current, below, result)
+                    },
+                    |first, _, receive| {
+                        to_first.send(*first).unwrap();
+                        *receive[0].as_deref_mut().unwrap() = from_first.recv().unwrap();
+                    },
+                );
+            });
+        });
+
+        upper.extend(lower);
+        assert_eq!(upper, reference(grid, rounds));
+    }
+}
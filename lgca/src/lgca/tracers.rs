@@ -0,0 +1,497 @@
+//! Passive tracer particles for measuring self-diffusion and drawing pathlines.
+//!
+//! A tracer rides on an occupied channel. Every round it moves like a single particle through the movement kernels
+//! of the simulation, including its boundary, and then moves on to the rank that owns its new row.
+//! Collisions make particles indistinguishable, so after a collision the tracer stays in its channel if it is still
+//! occupied and takes a random free occupied channel of its cell otherwise. Two tracers never share a channel.
+//! Tracers that leave through an open edge are dropped. Tracers that cross a periodic edge remember it, so their
+//! displacement keeps growing instead of jumping back.
+use mpi::{request::WaitGuard, traits::*};
+use rand::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+    ops::Range,
+    path::PathBuf,
+};
+
+use super::{
+    boundary::Boundary,
+    lattice::{Fhp, LatticeModel},
+    output::Output,
+    simulation::Simulation,
+};
+
+/// A tagged particle. Positions are global
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tracer {
+    pub id: u64,
+    pub x: usize,
+    pub y: usize,
+    /// Direction bit of the channel the tracer rides on
+    pub channel: u8,
+    pub start_x: usize,
+    pub start_y: usize,
+    /// Cells to add to the position to undo the wrapping at periodic edges
+    pub unwrap: (i64, i64),
+}
+
+/// Number of `u64` per tracer in messages between ranks
+const FIELDS: usize = 8;
+
+impl Tracer {
+    /// Squared distance in the plane from the start, across periodic edges
+    pub fn squared_displacement(&self) -> f64 {
+        let (x, y) = Fhp::position(self.x, self.y);
+        let (x, y) = (
+            x + self.unwrap.0 as f64,
+            y + self.unwrap.1 as f64 * Fhp::ROW_DISTANCE,
+        );
+        let (start_x, start_y) = Fhp::position(self.start_x, self.start_y);
+        (x - start_x).powi(2) + (y - start_y).powi(2)
+    }
+
+    /// Move to cell (`x`, `y`), which may lie one cell beyond an edge of the `width` x `height` grid
+    fn move_to(&mut self, x: isize, y: isize, channel: u8, width: usize, height: usize) {
+        let (width, height) = (width as isize, height as isize);
+        self.unwrap.0 += (x.div_euclid(width) * width) as i64;
+        self.unwrap.1 += (y.div_euclid(height) * height) as i64;
+        self.x = x.rem_euclid(width) as usize;
+        self.y = y.rem_euclid(height) as usize;
+        self.channel = channel;
+    }
+
+    fn to_message(self) -> [u64; FIELDS] {
+        [
+            self.id,
+            self.x as u64,
+            self.y as u64,
+            self.channel as u64,
+            self.start_x as u64,
+            self.start_y as u64,
+            self.unwrap.0 as u64,
+            self.unwrap.1 as u64,
+        ]
+    }
+
+    fn from_message(message: &[u64]) -> Self {
+        Self {
+            id: message[0],
+            x: message[1] as usize,
+            y: message[2] as usize,
+            channel: message[3] as u8,
+            start_x: message[4] as usize,
+            start_y: message[5] as usize,
+            unwrap: (message[6] as i64, message[7] as i64),
+        }
+    }
+}
+
+/// Where a single particle in `channel` of cell (`x`, `y`) arrives after one round of the movement kernels.
+///
+/// Returns the cell and the channel it arrives in, which differs from `channel` after a reflection,
+/// or `None` if the particle left through an open edge. The cell is not wrapped at periodic edges,
+/// so it can be one cell beyond the grid.
+pub fn destination<const WIDTH: usize>(
+    boundary: &dyn Boundary<WIDTH>,
+    global_height: usize,
+    x: usize,
+    y: usize,
+    channel: u8,
+) -> Option<(isize, isize, u8)> {
+    let direction = Fhp::DIRECTIONS
+        .iter()
+        .position(|direction| direction.bit == channel)
+        .unwrap();
+    let (offset_x, offset_y) = Fhp::neighbor_offset(direction, y);
+    let (neighbor_x, neighbor_y) = (x as isize + offset_x, y as isize + offset_y);
+    let inside_columns = boundary.periodic_columns() || (0..WIDTH as isize).contains(&neighbor_x);
+    let inside_rows = boundary.periodic_rows() || (0..global_height as isize).contains(&neighbor_y);
+    if inside_columns && inside_rows {
+        return Some((neighbor_x, neighbor_y, channel));
+    }
+    let top = y == 0 && !boundary.periodic_rows();
+    let bottom = y + 1 == global_height && !boundary.periodic_rows();
+    match boundary.edge_channel(x, y % 2 == 0, top, bottom, channel) {
+        0 => None,
+        channel => Some((x as isize, y as isize, channel)),
+    }
+}
+
+/// Whether a tracer in row `y` just left the `rows` of a rank through their top, wrapping around periodic rows.
+///
+/// Tracers move at most one row per round, so a tracer outside the rows is either just above or just below them.
+fn leaves_upwards(y: usize, rows: &Range<usize>, global_height: usize) -> bool {
+    y == (rows.start + global_height - 1) % global_height
+}
+
+/// Tracks tracers and writes their trajectories and the mean squared displacement.
+///
+/// Every rank writes the tracers it owns into `tracers_<rank>.csv`, the first rank writes `msd.csv`.
+pub struct Tracers {
+    count: usize,
+    directory: PathBuf,
+    random: SmallRng,
+    seed: u64,
+    pub tracers: Vec<Tracer>,
+    /// Mean squared displacement over all tracers after every round
+    pub msd: Vec<f64>,
+    /// Round of the first observed state
+    first_round: usize,
+    rank: i32,
+    trajectories: Option<BufWriter<File>>,
+}
+
+impl Tracers {
+    /// Place `count` tracers on random occupied channels, when the first state is observed
+    pub fn new(count: usize, seed: u64, directory: PathBuf) -> Self {
+        Self {
+            count,
+            directory,
+            random: SmallRng::seed_from_u64(seed),
+            seed,
+            tracers: Vec::new(),
+            msd: Vec::new(),
+            first_round: 0,
+            rank: 0,
+            trajectories: None,
+        }
+    }
+
+    /// Self-diffusion coefficient from the growth of the mean squared displacement over the second half of the run
+    pub fn diffusion_coefficient(&self) -> Option<f64> {
+        let last = self.msd.len().checked_sub(1)?;
+        let middle = last / 2;
+        if last == middle {
+            return None;
+        }
+        Some((self.msd[last] - self.msd[middle]) / (4.0 * (last - middle) as f64))
+    }
+
+    /// Place the tracers of this rank. Every rank gets a share proportional to its rows
+    fn place<const WIDTH: usize>(&mut self, simulation: &Simulation<WIDTH>) {
+        let global_height = simulation.global_height();
+        let first_id = self.count * simulation.row_offset() / global_height;
+        let end_id = self.count * (simulation.row_offset() + simulation.height()) / global_height;
+        let random = &mut SmallRng::seed_from_u64(self.seed ^ ((simulation.rank() as u64) << 32));
+        let grid = simulation.grid();
+        assert!(
+            simulation.particles() as usize >= end_id - first_id,
+            "Not enough particles for {} tracers",
+            self.count
+        );
+        let mut taken = HashSet::new();
+        for id in first_id..end_id {
+            loop {
+                let (x, y) = (random.gen_range(0..WIDTH), random.gen_range(0..grid.len()));
+                let channel = 1 << random.gen_range(0..6);
+                let global_y = y + simulation.row_offset();
+                if grid[y][x].raw & channel == 0 || !taken.insert((x, global_y, channel)) {
+                    continue;
+                }
+                self.tracers.push(Tracer {
+                    id: id as u64,
+                    x,
+                    y: global_y,
+                    channel,
+                    start_x: x,
+                    start_y: global_y,
+                    unwrap: (0, 0),
+                });
+                break;
+            }
+        }
+    }
+
+    /// Send tracers that left this rank to their neighbors and take the ones that arrive.
+    ///
+    /// With periodic rows the first and the last rank are neighbors too.
+    fn migrate<const WIDTH: usize>(&mut self, simulation: &Simulation<WIDTH>) {
+        let Some(communicator) = simulation.communicator() else {
+            return;
+        };
+        let rows = simulation.row_offset()..simulation.row_offset() + simulation.height();
+        let global_height = simulation.global_height();
+        let (mut up, mut down) = (Vec::new(), Vec::new());
+        self.tracers.retain(|tracer| {
+            if rows.contains(&tracer.y) {
+                true
+            } else if leaves_upwards(tracer.y, &rows, global_height) {
+                up.extend(tracer.to_message());
+                false
+            } else {
+                down.extend(tracer.to_message());
+                false
+            }
+        });
+
+        let neighbors = [simulation.previous_rank(), simulation.next_rank()];
+        let mut arrived: Vec<u64> = Vec::new();
+        mpi::request::scope(|scope| {
+            let mut guards = Vec::new();
+            for (neighbor, tracers) in neighbors.iter().zip([&up, &down]) {
+                if let Some(neighbor) = neighbor {
+                    let process = communicator.process_at_rank(*neighbor);
+                    guards.push(WaitGuard::from(process.immediate_send(scope, &tracers[..])));
+                }
+            }
+            for neighbor in neighbors.iter().flatten() {
+                let process = communicator.process_at_rank(*neighbor);
+                arrived.extend(process.receive_vec::<u64>().0);
+            }
+        });
+        self.tracers
+            .extend(arrived.chunks_exact(FIELDS).map(Tracer::from_message));
+    }
+
+    /// Put every tracer on an occupied channel of its cell after the collisions
+    fn collide<const WIDTH: usize>(&mut self, simulation: &Simulation<WIDTH>) {
+        let mut cells: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (index, tracer) in self.tracers.iter().enumerate() {
+            cells.entry((tracer.x, tracer.y)).or_default().push(index);
+        }
+        for ((x, y), indices) in cells {
+            let occupied = simulation.grid()[y - simulation.row_offset()][x].raw;
+            let mut claimed = 0u8;
+            let mut moved = Vec::new();
+            for index in indices {
+                let channel = self.tracers[index].channel;
+                if occupied & channel != 0 && claimed & channel == 0 {
+                    claimed |= channel;
+                } else {
+                    moved.push(index);
+                }
+            }
+            for index in moved {
+                let free: Vec<u8> = (0..6)
+                    .map(|bit| 1 << bit)
+                    .filter(|channel| occupied & !claimed & channel != 0)
+                    .collect();
+                let channel = *free
+                    .choose(&mut self.random)
+                    .expect("More tracers than particles in a cell");
+                claimed |= channel;
+                self.tracers[index].channel = channel;
+            }
+        }
+    }
+
+    fn record<const WIDTH: usize>(&mut self, simulation: &Simulation<WIDTH>) {
+        let trajectories = self.trajectories.get_or_insert_with(|| {
+            let path = self
+                .directory
+                .join(format!("tracers_{}.csv", simulation.rank()));
+            let mut file = BufWriter::new(File::create(path).unwrap());
+            writeln!(file, "round,id,x,y,channel").unwrap();
+            file
+        });
+        for tracer in &self.tracers {
+            writeln!(
+                trajectories,
+                "{},{},{},{},{}",
+                simulation.round(),
+                tracer.id,
+                tracer.x,
+                tracer.y,
+                tracer.channel
+            )
+            .unwrap();
+        }
+
+        let local = [
+            self.tracers
+                .iter()
+                .map(Tracer::squared_displacement)
+                .sum::<f64>(),
+            self.tracers.len() as f64,
+        ];
+        let total = simulation.sum_over_ranks(&local);
+        self.msd.push(total[0] / total[1].max(1.0));
+    }
+}
+
+impl<const WIDTH: usize> Output<WIDTH> for Tracers
+where
+    [(); WIDTH - 1]:,
+    [(); WIDTH - 2]:,
+{
+    fn observe(&mut self, simulation: &Simulation<WIDTH>) {
+        if self.msd.is_empty() {
+            self.first_round = simulation.round();
+            self.rank = simulation.rank();
+            self.place(simulation);
+            self.record(simulation);
+            return;
+        }
+        self.tracers.retain_mut(|tracer| {
+            let Some(destination) = destination(
+                simulation.boundary(),
+                simulation.global_height(),
+                tracer.x,
+                tracer.y,
+                tracer.channel,
+            ) else {
+                return false;
+            };
+            let (x, y, channel) = destination;
+            tracer.move_to(x, y, channel, WIDTH, simulation.global_height());
+            true
+        });
+        self.migrate(simulation);
+        self.collide(simulation);
+        self.record(simulation);
+    }
+
+    fn finish(&mut self) {
+        if let Some(trajectories) = self.trajectories.as_mut() {
+            trajectories.flush().unwrap();
+        }
+        if self.rank != 0 {
+            return;
+        }
+        let mut file = BufWriter::new(File::create(self.directory.join("msd.csv")).unwrap());
+        writeln!(file, "round,msd").unwrap();
+        for (round, msd) in self.msd.iter().enumerate() {
+            writeln!(file, "{},{}", self.first_round + round, msd).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lgca::{
+        boundary::{Edge, EdgeBoundary, ReflectingBoundary},
+        cell::{TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_WEST, TO_WEST},
+        testing::TempDir,
+    };
+
+    #[test]
+    fn destination_follows_the_kernels() {
+        let boundary = ReflectingBoundary;
+        let destination = |x, y, channel| destination::<10>(&boundary, 8, x, y, channel).unwrap();
+        assert_eq!(destination(4, 4, TO_NORTH_EAST), (5, 3, TO_NORTH_EAST));
+        assert_eq!(destination(4, 3, TO_NORTH_EAST), (4, 2, TO_NORTH_EAST));
+        assert_eq!(destination(4, 3, TO_SOUTH_WEST), (3, 4, TO_SOUTH_WEST));
+        assert_eq!(destination(4, 3, TO_WEST), (3, 3, TO_WEST));
+        // Reflected at the top wall and the west wall
+        assert_eq!(destination(4, 0, TO_NORTH_WEST).2 & TO_NORTH_WEST, 0);
+        assert_eq!(destination(0, 3, TO_WEST), (0, 3, TO_WEST << 3));
+
+        let periodic = EdgeBoundary::new(Edge::Periodic, Edge::Periodic, Edge::Open, Edge::Open);
+        let wrapped = |x, y, channel| super::destination::<10>(&periodic, 8, x, y, channel);
+        assert_eq!(wrapped(4, 0, TO_NORTH_WEST), Some((4, -1, TO_NORTH_WEST)));
+        // Lost through the open west edge
+        assert_eq!(wrapped(0, 3, TO_WEST), None);
+    }
+
+    #[test]
+    fn destination_matches_a_step_of_a_lone_particle() {
+        const WIDTH: usize = 6;
+        let boundaries = [
+            [Edge::Reflecting; 4],
+            [Edge::BounceBack, Edge::Open, Edge::Reflecting, Edge::Open],
+            [
+                Edge::Periodic,
+                Edge::Periodic,
+                Edge::BounceBack,
+                Edge::Reflecting,
+            ],
+            [Edge::Periodic; 4],
+        ];
+        for [north, south, west, east] in boundaries {
+            let boundary = EdgeBoundary::new(north, south, west, east);
+            let cells = (0..8).flat_map(|y| (0..WIDTH).map(move |x| (x, y)));
+            for ((x, y), direction) in cells.flat_map(|cell| (0..6).map(move |d| (cell, d))) {
+                let channel = 1 << direction;
+                let mut simulation = Simulation::<WIDTH>::new(8, None);
+                simulation.set_boundary(Box::new(EdgeBoundary::new(north, south, west, east)));
+                simulation.grid_mut()[y][x].raw = channel;
+                // Particles coming in through open edges could collide with it
+                simulation.propagate();
+                match destination::<WIDTH>(&boundary, 8, x, y, channel) {
+                    Some((new_x, new_y, new_channel)) => {
+                        let cell = simulation.grid()[new_y.rem_euclid(8) as usize]
+                            [new_x.rem_euclid(WIDTH as isize) as usize];
+                        assert_ne!(cell.raw & new_channel, 0, "{} from ({}, {})", channel, x, y);
+                    }
+                    None => assert_eq!(simulation.particles(), 0),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tracer_follows_a_lone_particle() {
+        let mut simulation = Simulation::<12>::new(9, None);
+        simulation.grid_mut()[4][5].raw = TO_NORTH_EAST;
+        let directory = TempDir::new("tracers");
+        let mut tracers = Tracers::new(1, 3, directory.path().to_path_buf());
+
+        simulation.observe(&mut [&mut tracers]);
+        for _ in 0..25 {
+            simulation.step();
+            simulation.observe(&mut [&mut tracers]);
+            let tracer = tracers.tracers[0];
+            let cell = simulation.grid()[tracer.y][tracer.x];
+            assert_eq!(cell.raw, tracer.channel);
+        }
+        Output::<12>::finish(&mut tracers);
+        assert_eq!(tracers.msd.len(), 26);
+        assert_eq!(tracers.msd[0], 0.0);
+    }
+
+    #[test]
+    fn tracers_wrap_to_the_rank_across_periodic_rows() {
+        // Three ranks with the rows 0..4, 4..8 and 8..12
+        assert!(leaves_upwards(11, &(0..4), 12));
+        assert!(!leaves_upwards(4, &(0..4), 12));
+        assert!(leaves_upwards(3, &(4..8), 12));
+        assert!(!leaves_upwards(0, &(8..12), 12));
+        assert!(leaves_upwards(7, &(8..12), 12));
+    }
+
+    #[test]
+    fn displacements_grow_across_periodic_edges() {
+        let mut simulation = Simulation::<12>::new(8, None);
+        simulation.set_boundary(Box::new(EdgeBoundary::new(
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+            Edge::Periodic,
+        )));
+        simulation.grid_mut()[3][2].raw = TO_NORTH_WEST;
+        let directory = TempDir::new("tracers_wrap");
+        let mut tracers = Tracers::new(1, 3, directory.path().to_path_buf());
+
+        simulation.run(16, &mut [&mut tracers]);
+        let tracer = tracers.tracers[0];
+        assert_eq!(simulation.grid()[tracer.y][tracer.x].raw, TO_NORTH_WEST);
+        assert_eq!(tracer.unwrap, (-12, -16));
+        // 16 rounds of one cell each in a straight line
+        assert!(
+            (tracers.msd[16] - 256.0).abs() < 1e-9,
+            "{}",
+            tracers.msd[16]
+        );
+    }
+
+    #[test]
+    fn tracers_stay_on_distinct_occupied_channels() {
+        let mut simulation = Simulation::<16>::new(12, None);
+        simulation.add_seeded_noise(0.4, 1);
+        let directory = TempDir::new("tracers_many");
+        let mut tracers = Tracers::new(40, 3, directory.path().to_path_buf());
+
+        simulation.run(30, &mut [&mut tracers]);
+        assert_eq!(tracers.tracers.len(), 40);
+        let mut channels = HashSet::new();
+        for tracer in &tracers.tracers {
+            let cell = simulation.grid()[tracer.y][tracer.x];
+            assert_ne!(cell.raw & tracer.channel, 0);
+            assert!(channels.insert((tracer.x, tracer.y, tracer.channel)));
+        }
+        assert!(tracers.msd[30] > 0.0);
+    }
+}
@@ -1,5 +1,9 @@
 use hsv::hsv_to_rgb;
-use ril::{Image, Rgb, TrueColor};
+use ril::{
+    encodings::webp::{WebPEncoderOptions, WebPMuxEncoder},
+    Encoder, EncoderMetadata, Frame, Image, ImageSequence, Rgb, TrueColor,
+};
+use std::{fs::File, time::Duration};
 
 use super::Cell;
 
@@ -53,9 +57,16 @@ pub fn cells_to_color(cells: &[&Cell]) -> Rgb {
     //     _ => 0.0,
     // };
 
+    flow_to_color(angle, length as f64 / cells.len() as f64, density)
+}
+
+/// Hue for the direction, saturation for the mean speed and brightness for the density of a flow.
+///
+/// The angle is in quarter turns like the one of `get_direction_of_cells`.
+pub fn flow_to_color(angle: f32, speed: f64, density: f64) -> Rgb {
     let color = hsv_to_rgb(
         ((angle * 90.0) + 180.0) as f64,
-        (2.0 * length as f64 / cells.len() as f64).min(1.0),
+        (2.0 * speed).min(1.0),
         (density * 6.0).min(1.0),
     );
 
@@ -93,6 +104,59 @@ pub fn draw_cells_detailed<const WIDTH: usize>(cells: &[[Cell; WIDTH]]) -> Image
     return image;
 }
 
+/// Red and blue for the two species, brighter for more particles
+pub fn species_to_color(cell: &Cell, colors: &Cell) -> Rgb {
+    let particles = cell.get_particles();
+    if particles == 0 {
+        return Rgb::black();
+    }
+    let red = colors.get_particles() as f64 / particles as f64;
+    let brightness = (particles as f64 / 3.0).min(1.0) * 255.0;
+
+    Rgb::new(
+        (red * brightness) as u8,
+        0,
+        ((1.0 - red) * brightness) as u8,
+    )
+}
+
+pub fn draw_species<const WIDTH: usize>(
+    cells: &[[Cell; WIDTH]],
+    colors: &[[Cell; WIDTH]],
+) -> Image<Rgb> {
+    let mut image = Image::new(WIDTH as u32, cells.len() as u32, Rgb::black());
+
+    for (y, (row, color_row)) in cells.iter().zip(colors.iter()).enumerate() {
+        for (x, (pixel, colors)) in row.iter().zip(color_row.iter()).enumerate() {
+            image.set_pixel(x as u32, y as u32, species_to_color(pixel, colors));
+        }
+    }
+    return image;
+}
+
+/// Encode the images as a lossless animated WebP
+pub fn save_webp(images: Vec<Image<Rgb>>, time_per_frame: Duration, filename: &str) {
+    let mut output = ImageSequence::<Rgb>::new();
+
+    // ImageSequence::open is lazy
+    for frame in images {
+        let mut frame = Frame::from_image(frame);
+        frame.set_delay(time_per_frame);
+        output.push_frame(frame);
+    }
+
+    eprintln!("Saving output to {}", filename);
+
+    let options = WebPEncoderOptions::new().with_lossless(true);
+    let f = File::create(filename).expect("Create file");
+    let mut encoder = WebPMuxEncoder::new(f, EncoderMetadata::from(&output).with_config(options))
+        .expect("new encoder");
+    for frame in output.iter() {
+        encoder.add_frame(frame).expect("adding frame");
+    }
+    encoder.finish().expect("finish");
+}
+
 #[allow(dead_code)]
 pub fn draw_cells_b<const WIDTH: usize>(cells: &[[Cell; WIDTH]]) -> Image<Rgb> {
     let mut image = Image::new(cells.len() as u32, WIDTH as u32, Rgb::black());
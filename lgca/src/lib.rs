@@ -0,0 +1,23 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+#![feature(array_chunks)]
+#![feature(iter_map_windows)]
+#![feature(array_windows)]
+#![feature(new_uninit)]
+#![feature(split_array)]
+#![feature(stmt_expr_attributes)]
+#![feature(inline_const_pat)]
+
+mod lgca;
+pub use lgca::*;
+
+/// Number of cells in a row. Selected at compile time with `--cfg width_*`
+pub const WIDTH: usize = if cfg!(width_100000) {
+    100000
+} else if cfg!(width_10000) {
+    10000
+} else if cfg!(width_1000) {
+    1000
+} else {
+    100
+};
@@ -1,39 +1,30 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
-#![feature(array_chunks)]
-#![feature(iter_map_windows)]
-#![feature(array_windows)]
-#![feature(new_uninit)]
-#![feature(split_array)]
-#![feature(stmt_expr_attributes)]
-#![feature(inline_const_pat)]
-
-mod lgca;
-use crate::lgca::{
-    cell::{TO_EAST, TO_NORTH_EAST, TO_NORTH_WEST, TO_SOUTH_EAST, TO_SOUTH_WEST, TO_WEST},
-    new_movements::{movement_bottom_row, movement_even_row, movement_odd_row, movement_top_row},
-    visualization::draw_cells_detailed,
+
+use clap::{Parser, Subcommand};
+use lgca::{
+    boundary::{Edge, EdgeBoundary},
+    ensemble::Ensemble,
+    experiments::Experiment,
+    forcing::BodyForce,
+    init_image::read_initial_image,
+    io_rank::{split_io_rank, StripReceiver, StripSender},
+    obstacles::{ObstacleForces, Shape},
+    output::{Output, WebPRenderer, Y4mRenderer},
+    porous::PorousMedium,
+    preview::LivePreview,
+    probes::{Probe, Probes},
+    reversal::TimeReversal,
+    simulation::Simulation,
+    snapshot::{read_snapshot, SnapshotWriter},
+    statistics::CollisionStatistics,
+    terminal::TerminalViewer,
+    tiling::TemporalBlocking,
+    tracers::Tracers,
+    WIDTH,
 };
-use clap::Parser;
-use lgca::Cell;
-use mpi::request::WaitGuard;
-use mpi::topology::SimpleCommunicator;
 use mpi::traits::*;
-use rand::prelude::*;
-use rayon::prelude::*;
-use rayon::{
-    iter::{IndexedParallelIterator, IntoParallelRefMutIterator},
-    slice::ParallelSlice,
-};
-use ril::{
-    encodings::webp::{WebPEncoderOptions, WebPMuxEncoder},
-    Encoder, EncoderMetadata, Frame, Image, ImageSequence, Rgb,
-};
-use std::{
-    fs::File,
-    path::PathBuf,
-    time::{Duration, Instant},
-};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -57,6 +48,12 @@ struct Cli {
     #[arg(short, long, default_value_t = 60)]
     framerate: usize,
 
+    /// Stream the frames as uncompressed YUV4MPEG2 video into this file in the output directory instead of an
+    /// animated WebP. With several ranks every rank writes its rows into a file with its rank appended to the name.
+    /// `-` streams to stdout and only works with a single rank
+    #[arg(long, value_name = "FILE")]
+    y4m: Option<PathBuf>,
+
     /// Scaling factor of the output video
     #[arg(long, default_value_t = 0.26)]
     scaling: f64,
@@ -65,13 +62,139 @@ struct Cli {
     #[arg(short, long, default_value_t = 1)]
     threads: usize,
 
-    /// Number of rows (Divided by number of mpi ranks)
-    #[arg(long, default_value_t = 1)]
+    /// Number of rows of the whole grid, split as evenly as possible across the mpi ranks
+    #[arg(long, default_value_t = 2)]
     height: usize,
 
     /// Size of the initially filled box
     #[arg(long, default_value_t = 500)]
     boxx: usize,
+
+    /// Probability per cell and round that a particle gets flipped to the east
+    #[arg(long, default_value_t = 0.0)]
+    body_force: f64,
+
+    /// Number of independent realizations. The MPI ranks are split evenly between them.
+    /// Every member writes its files into member_<index> in the output directory
+    #[arg(long, default_value_t = 1)]
+    ensemble: usize,
+
+    /// Seed of the initial noise, the tracers and the particles sampled from --init-image. Every ensemble member
+    /// adds its index. Random if not set
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Size of the blocks the ensemble fields are averaged over
+    #[arg(long, default_value_t = 10)]
+    block_size: usize,
+
+    /// Write a snapshot of the whole grid every nth round into the output directory. 0 disables snapshots
+    #[arg(long, default_value_t = 0)]
+    snapshot_interval: usize,
+
+    /// Start from a snapshot instead of the filled box. The height is taken from the snapshot
+    #[arg(long)]
+    restore: Option<PathBuf>,
+
+    /// Start from a PNG instead of the filled box. Brightness sets the density, hue and saturation the flow
+    #[arg(long)]
+    init_image: Option<PathBuf>,
+
+    /// Show the simulation in the terminal instead of writing a WebP. Starts at --speed and only works without MPI
+    #[arg(long)]
+    interactive: bool,
+
+    /// Run the rounds forward and backward with deterministic collisions from this seed
+    /// and check that the initial state comes back. This checks the movement and the borders,
+    /// the random collisions of the normal rounds can not be reversed
+    #[arg(long)]
+    time_reversal: Option<u64>,
+
+    /// Advance tiles of rows this many rounds at once to save memory bandwidth. 0 disables temporal blocking.
+    /// Can not be combined with outputs
+    #[arg(long, default_value_t = 0)]
+    temporal_blocking: usize,
+
+    /// Update the grid in place instead of calculating every round into a second grid. Halves the memory
+    #[arg(long)]
+    in_place: bool,
+
+    /// Rules for the top edge: reflecting, bounce-back, periodic, open or moving:<speed>
+    #[arg(long, default_value = "reflecting")]
+    north: Edge,
+
+    /// Rules for the bottom edge. Periodic only together with a periodic north edge and an even height
+    #[arg(long, default_value = "reflecting")]
+    south: Edge,
+
+    /// Rules for the left edge. Moving walls at the west and east edge move to the south
+    #[arg(long, default_value = "reflecting")]
+    west: Edge,
+
+    /// Rules for the right edge. Periodic only together with a periodic west edge
+    #[arg(long, default_value = "reflecting")]
+    east: Edge,
+
+    /// Number of tracer particles. Their trajectories and the mean squared displacement are written into the output directory
+    #[arg(long, default_value_t = 0)]
+    tracers: usize,
+
+    /// Write the collision counts and the state histogram of every round into collisions.csv in the output directory
+    #[arg(long)]
+    collision_statistics: bool,
+
+    /// Record density and velocity every round at x,y or in the region x,y,width,height into probe_<index>.csv.
+    /// Can be given multiple times
+    #[arg(long)]
+    probe: Vec<Probe>,
+
+    /// Put a solid body circle:x,y,radius or rectangle:x,y,width,height into the flow. Can be given multiple times.
+    /// The drag and lift on every body are written into forces.csv in the output directory
+    #[arg(long)]
+    obstacle: Vec<Shape>,
+
+    /// Split the particles into two immiscible species with this fraction of red particles. Uses --seed.
+    /// Can not be combined with a body force, obstacles, moving walls, temporal blocking or --restore
+    #[arg(long)]
+    red_fraction: Option<f64>,
+
+    /// Fill the grid with random grains until this fraction of the cells is still fluid. Uses --seed
+    #[arg(long)]
+    porosity: Option<f64>,
+
+    /// Radius of the grains placed for --porosity in cell widths
+    #[arg(long, default_value_t = 4.0)]
+    grain_radius: f64,
+
+    /// Serve a live preview with the latest frame and the statistics of the run on this port of localhost
+    #[arg(long)]
+    preview_port: Option<u16>,
+
+    /// Rounds between two frames of the live preview
+    #[arg(long, default_value_t = 100)]
+    preview_interval: usize,
+
+    /// Leave the last rank out of the simulation. It renders the frames of all other ranks into a single output.webp
+    #[arg(long)]
+    io_rank: bool,
+
+    /// Back the grids with transparent huge pages
+    #[arg(long)]
+    huge_pages: bool,
+
+    /// Rows per tile of the temporal blocking. Needs at least twice as many rows as rounds per tile
+    #[arg(long, default_value_t = 16)]
+    tile_height: usize,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a physical experiment instead of the default simulation
+    #[command(subcommand)]
+    Experiment(Experiment),
 }
 
 fn main() {
@@ -81,33 +204,56 @@ fn main() {
     } else {
         None
     };
+    let communicator = mpi_universe.as_ref().map(|(universe, _)| universe.world());
+
+    let size = communicator.as_ref().map_or(1, |world| world.size());
+    let rank = communicator.as_ref().map_or(0, |world| world.rank());
 
-    let size = mpi_universe
-        .as_ref()
-        .map_or(1, |o: &(mpi::environment::Universe, mpi::Threading)| {
-            o.0.world().size()
-        });
-    let rank = mpi_universe.as_ref().map_or(0, |o| o.0.world().rank());
-    let previous_rank: Option<i32> = if rank == 0 { None } else { Some(rank - 1) };
-    let next_rank: Option<i32> = if rank == (size - 1) as i32 {
+    let cli = Cli::parse();
+
+    let ensemble = if cli.ensemble > 1 {
+        let world = communicator
+            .as_ref()
+            .expect("Ensembles need to be started with MPI");
+        Some(Ensemble::split(world, cli.ensemble))
+    } else {
         None
+    };
+    let member = ensemble.as_ref().map_or(0, |ensemble| ensemble.member);
+    let (communicator, size) = match &ensemble {
+        Some(ensemble) => (
+            Some(ensemble.communicator.duplicate()),
+            ensemble.communicator.size(),
+        ),
+        None => (communicator, size),
+    };
+    let world = mpi_universe.as_ref().map(|(universe, _)| universe.world());
+    let io_communicator = if cli.io_rank {
+        if ensemble.is_some() || cli.y4m.is_some() || cli.framerate == 0 {
+            panic!("The I/O rank only renders the WebP of a single simulation, it can not be combined with --ensemble, --y4m or --framerate 0");
+        }
+        if cli.time_reversal.is_some() || cli.interactive {
+            panic!("The I/O rank needs a normal run, it can not be combined with --time-reversal or --interactive");
+        }
+        let world = world
+            .as_ref()
+            .expect("The I/O rank needs to be started with MPI");
+        Some(split_io_rank(world))
     } else {
-        Some(rank + 1)
+        None
+    };
+    let is_io_rank = matches!(io_communicator, Some(None));
+    let (communicator, size) = match io_communicator {
+        Some(Some(compute)) => {
+            let size = compute.size();
+            (Some(compute), size)
+        }
+        _ => (communicator, size),
     };
-
-    let cli = Cli::parse();
 
     let rounds = cli.rounds;
-    let noise = cli.noise;
     let threads = cli.threads;
-    let image_scaling = cli.scaling;
-    let rounds_per_second = cli.speed;
     let frames_per_second = cli.framerate;
-    let time_per_round = Duration::from_secs_f64(1.0 / rounds_per_second as f64);
-    let time_per_frame = Duration::from_secs_f64(1.0 / (frames_per_second as f64).max(1.0));
-    let height = (cli.height.div_ceil(size as usize).div_ceil(2)) * 2 as usize;
-    let filepath = cli.output_directory.join(format!("output_{}.webp", rank));
-    let filename = filepath.to_str().unwrap();
 
     if !cli.output_directory.is_dir() {
         if cli.output_directory.exists() {
@@ -115,209 +261,237 @@ fn main() {
         }
         std::fs::create_dir_all(&cli.output_directory).unwrap();
     }
-
-    const WIDTH: usize = if cfg!(width_100000) {
-        100000
-    } else if cfg!(width_10000) {
-        10000
-    } else if cfg!(width_1000) {
-        1000
-    } else {
-        100
+    // The members of an ensemble write files with the same names, so each one gets its own directory
+    let output_directory = match &ensemble {
+        Some(ensemble) => {
+            let directory = ensemble.member_directory(&cli.output_directory);
+            std::fs::create_dir_all(&directory).unwrap();
+            directory
+        }
+        None => cli.output_directory.clone(),
     };
+    let filepath = output_directory.join(format!("output_{}.webp", rank));
+    let video_path = cli.y4m.as_ref().map(|path| {
+        if path.as_os_str() == "-" {
+            if size != 1 || ensemble.is_some() {
+                panic!("Only a single rank can stream the video to stdout");
+            }
+            return path.clone();
+        }
+        if size == 1 {
+            return output_directory.join(path);
+        }
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("_{}", rank));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        output_directory.join(path.with_file_name(name))
+    });
+
     // Put the correct number of threads into rayons global thread pool
     rayon::ThreadPoolBuilder::new()
         .num_threads(threads)
         .build_global()
         .unwrap();
 
-    // Create a sample section
-    let mut sections_box = vec![[Cell::new(); WIDTH]; height];
-    let mut sections_b_box = vec![[Cell::new(); WIDTH]; height];
-    let mut grid_a: &mut [[Cell; WIDTH]] = sections_box.as_mut();
-    let mut grid_b: &mut [[Cell; WIDTH]] = sections_b_box.as_mut();
-
-    let box_y = cli
-        .boxx
-        .saturating_sub(height * (rank as usize))
-        .min(height);
-    let box_x = cli.boxx.min(WIDTH);
-
-    grid_a[1][1].raw = 0b00111111;
-    if previous_rank.is_none() {
-        for x in 0..box_x {
-            for y in 0..box_y {
-                grid_a[y][x].raw = 0b00111111;
-            }
+    if let Some(Command::Experiment(experiment)) = &cli.command {
+        // Experiments are small, so they only run on the first rank
+        if rank == 0 {
+            experiment.run::<WIDTH>();
         }
+        return;
     }
 
-    let random = &mut rand::thread_rng();
-    for y in 0..height {
-        for x in 0..WIDTH {
-            if random.gen_bool(noise) {
-                grid_a[y][x].raw ^= TO_EAST;
-            }
-            if random.gen_bool(noise) {
-                grid_a[y][x].raw ^= TO_NORTH_EAST;
-            }
-            if random.gen_bool(noise) {
-                grid_a[y][x].raw ^= TO_NORTH_WEST;
-            }
-            if random.gen_bool(noise) {
-                grid_a[y][x].raw ^= TO_SOUTH_WEST;
-            }
-            if random.gen_bool(noise) {
-                grid_a[y][x].raw ^= TO_SOUTH_EAST;
-            }
-            if random.gen_bool(noise) {
-                grid_a[y][x].raw ^= TO_WEST;
-            }
-        }
-    }
-    eprintln!("============================ Round 0");
-    let mut images: Vec<Image<Rgb>> = Vec::new();
-    if frames_per_second != 0 {
-        images.push(draw_cells_detailed(grid_a).resized(
-            (WIDTH as f64 * image_scaling) as u32,
-            (height as f64 * image_scaling) as u32,
-            ril::ResizeAlgorithm::Lanczos3,
-        ));
+    if is_io_rank {
+        let receiver = StripReceiver::new(world.unwrap(), frames_per_second, cli.scaling);
+        let frames = receiver.run::<WIDTH>(&cli.output_directory.join("output.webp"));
+        eprintln!("The I/O rank wrote {} frames", frames);
+        return;
     }
-    let mut top_bottom_duration: Duration = Duration::new(0, 0);
-    let mut core_duration: Duration = Duration::new(0, 0);
-    let mut communication_duration: Duration = Duration::new(0, 0);
-    let mut render_duration: Duration = Duration::new(0, 0);
-
-    let mut receive_top_box = Box::new([Cell::new(); WIDTH]);
-    let mut receive_bottom_box = Box::new([Cell::new(); WIDTH]);
-    let receive_top = receive_top_box.as_mut();
-    let receive_bottom = receive_bottom_box.as_mut();
-
-    let mut gif_time = Duration::new(0, 0);
-    for round in 0..rounds {
-        // process_round(grid_a, grid_b);
-        let communication_time = Instant::now();
-        if mpi_universe.is_some() {
-            let world = SimpleCommunicator::world();
-            mpi::request::scope(|scope| {
-                let mut guards = Vec::new();
-
-                if let Some(previous_rank) = previous_rank {
-                    guards.push(WaitGuard::from(
-                        world
-                            .process_at_rank(previous_rank)
-                            .immediate_send(scope, unsafe {
-                                std::mem::transmute::<&mut [Cell; WIDTH], &mut [u8; WIDTH]>(
-                                    &mut grid_a[0],
-                                )
-                            }),
-                    ));
-                    guards.push(WaitGuard::from(
-                        world.process_at_rank(previous_rank).immediate_receive_into(
-                            scope,
-                            unsafe {
-                                std::mem::transmute::<&mut [Cell; WIDTH], &mut [u8; WIDTH]>(
-                                    receive_top,
-                                )
-                            },
-                        ),
-                    ));
-                }
-
-                if let Some(next_rank) = next_rank {
-                    guards.push(WaitGuard::from(
-                        world
-                            .process_at_rank(next_rank)
-                            .immediate_send(scope, unsafe {
-                                std::mem::transmute::<&mut [Cell; WIDTH], &mut [u8; WIDTH]>(
-                                    &mut grid_a[height - 1],
-                                )
-                            }),
-                    ));
-                    guards.push(WaitGuard::from(
-                        world
-                            .process_at_rank(next_rank)
-                            .immediate_receive_into(scope, unsafe {
-                                std::mem::transmute::<&mut [Cell; WIDTH], &mut [u8; WIDTH]>(
-                                    receive_bottom,
-                                )
-                            }),
-                    ));
-                }
-            });
-        }
-        communication_duration += communication_time.elapsed();
 
-        let round_timer = Instant::now();
-        if previous_rank.is_some() {
-            movement_even_row(receive_top, &grid_a[0], &grid_a[1], &mut grid_b[0]);
+    let mut simulation = if let Some(snapshot) = &cli.restore {
+        read_snapshot::<WIDTH>(snapshot, communicator)
+    } else if let Some(image) = &cli.init_image {
+        let mut simulation =
+            Simulation::<WIDTH>::new_with_huge_pages(cli.height, communicator, cli.huge_pages);
+        let seed = if cli.seed.is_some() || ensemble.is_some() {
+            cli.seed.unwrap_or(0) + member as u64
+        } else {
+            rand::random()
+        };
+        read_initial_image(&mut simulation, image, seed);
+        simulation
+    } else {
+        let mut simulation =
+            Simulation::<WIDTH>::new_with_huge_pages(cli.height, communicator, cli.huge_pages);
+        simulation.grid_mut()[1][1].raw = 0b00111111;
+        simulation.fill_box(cli.boxx);
+        if cli.seed.is_some() || ensemble.is_some() {
+            simulation.add_seeded_noise(cli.noise, cli.seed.unwrap_or(0) + member as u64);
         } else {
-            movement_top_row(&grid_a[0], &grid_a[1], &mut grid_b[0]);
+            simulation.add_noise(cli.noise);
         }
-        top_bottom_duration += round_timer.elapsed();
-
-        let round_timer = Instant::now();
-        grid_a
-            .par_windows(3)
-            .zip(grid_b.par_iter_mut().skip(1))
-            .enumerate()
-            .for_each(|(row_index, (context, result))| {
-                let above = &context[0];
-                let current = &context[1];
-                let below = &context[2];
-                if ((row_index + 1) % 2) == 0 {
-                    movement_even_row(above, current, below, result);
-                } else {
-                    movement_odd_row(above, current, below, result);
-                }
-            });
-
-        core_duration += round_timer.elapsed();
-
-        let round_timer = Instant::now();
-        if next_rank.is_some() {
-            movement_odd_row(
-                &grid_a[height - 2],
-                &grid_a[height - 1],
-                receive_bottom,
-                &mut grid_b[height - 1],
+        simulation
+    };
+    if cli.in_place && (!cli.obstacle.is_empty() || cli.porosity.is_some()) {
+        panic!("Obstacles need the old round in the second grid, they can not be combined with --in-place");
+    }
+    let height = simulation.height();
+    let global_height = simulation.global_height();
+    for probe in &cli.probe {
+        probe.assert_inside(WIDTH, global_height);
+    }
+    simulation.set_in_place(cli.in_place);
+    let edges = [cli.north, cli.south, cli.west, cli.east];
+    if edges.iter().any(|edge| *edge != Edge::Reflecting) {
+        simulation.set_boundary(Box::new(EdgeBoundary::new(
+            cli.north, cli.south, cli.west, cli.east,
+        )));
+    }
+    simulation.set_body_force(BodyForce::new(cli.body_force));
+    let mut obstacles = cli.obstacle.clone();
+    if let Some(porosity) = cli.porosity {
+        let medium = PorousMedium {
+            porosity,
+            radius: cli.grain_radius,
+            seed: cli.seed.unwrap_or(0),
+        };
+        obstacles.extend(medium.generate(WIDTH, global_height));
+    }
+    if !obstacles.is_empty() {
+        simulation.set_obstacles(obstacles);
+    }
+    if let Some(red_fraction) = cli.red_fraction {
+        if !(0.0..=1.0).contains(&red_fraction) {
+            panic!("The fraction of red particles must be between 0 and 1");
+        }
+        if cli.body_force != 0.0 || !simulation.obstacles().is_empty() || cli.temporal_blocking != 0
+        {
+            panic!(
+                "Two species can not be combined with a body force, obstacles or temporal blocking"
             );
-        } else {
-            movement_bottom_row(
-                &grid_a[height - 2],
-                &grid_a[height - 1],
-                &mut grid_b[height - 1],
+        }
+        if cli.restore.is_some() {
+            panic!("Snapshots do not store the species, --red-fraction can not be combined with --restore");
+        }
+        if edges.iter().any(|edge| matches!(edge, Edge::MovingWall(_))) {
+            panic!("The colors of two species can not follow particles at moving walls");
+        }
+        simulation.set_species(red_fraction, cli.seed.unwrap_or(0) + member as u64);
+    }
+
+    if let Some(seed) = cli.time_reversal {
+        let differences = TimeReversal::new(seed).run(&mut simulation, rounds);
+        if rank == 0 {
+            if differences != 0 {
+                panic!(
+                    "Time reversal of {} rounds failed, {} cells differ from the initial state",
+                    rounds, differences
+                );
+            }
+            eprintln!(
+                "Time reversal of {} rounds restored the initial state",
+                rounds
             );
         }
-        std::mem::swap(&mut grid_a, &mut grid_b);
-        top_bottom_duration += round_timer.elapsed();
+        return;
+    }
+
+    if cli.interactive {
+        if size != 1 {
+            panic!("The interactive mode only works with a single rank");
+        }
+        TerminalViewer::new(cli.speed as f64).run(&mut simulation, rounds);
+        return;
+    }
 
-        if frames_per_second == 0 {
-            continue;
+    eprintln!("============================ Round 0");
+    if frames_per_second != 0 && cli.speed == 0 {
+        panic!("Videos need a positive --speed, only --interactive can start paused");
+    }
+    let mut sender = world
+        .filter(|_| cli.io_rank)
+        .map(|world| StripSender::new(world, cli.speed, frames_per_second, cli.scaling));
+    let mut video = video_path
+        .filter(|_| frames_per_second != 0 && sender.is_none())
+        .map(|path| Y4mRenderer::new(path, cli.speed, frames_per_second, cli.scaling));
+    let mut renderer = (frames_per_second != 0 && sender.is_none() && video.is_none())
+        .then(|| WebPRenderer::new(filepath, cli.speed, frames_per_second, cli.scaling));
+    // At most one of them exists
+    let mut outputs: Vec<&mut dyn Output<WIDTH>> = Vec::new();
+    if let Some(sender) = sender.as_mut() {
+        outputs.push(sender);
+    }
+    if let Some(video) = video.as_mut() {
+        outputs.push(video);
+    }
+    if let Some(renderer) = renderer.as_mut() {
+        outputs.push(renderer);
+    }
+    let mut snapshots = SnapshotWriter::new(output_directory.clone(), cli.snapshot_interval.max(1));
+    if cli.snapshot_interval != 0 {
+        outputs.push(&mut snapshots);
+    }
+    let mut tracers = Tracers::new(
+        cli.tracers,
+        cli.seed.unwrap_or(0) + member as u64,
+        output_directory.clone(),
+    );
+    if cli.tracers != 0 {
+        outputs.push(&mut tracers);
+    }
+    let mut statistics = CollisionStatistics::new(output_directory.join("collisions.csv"));
+    if cli.collision_statistics {
+        outputs.push(&mut statistics);
+    }
+    let mut probes = Probes::new(cli.probe.clone(), output_directory.clone());
+    if !cli.probe.is_empty() {
+        outputs.push(&mut probes);
+    }
+    let mut preview = LivePreview::new(
+        cli.preview_port.unwrap_or(0),
+        cli.preview_interval,
+        cli.scaling,
+    );
+    // Every ensemble member would need its own port, so only the first one is shown
+    if cli.preview_port.is_some() && member == 0 {
+        outputs.push(&mut preview);
+    }
+    let mut forces = ObstacleForces::new(output_directory.join("forces.csv"));
+    if !simulation.obstacles().is_empty() {
+        outputs.push(&mut forces);
+    }
+    if cli.temporal_blocking == 0 {
+        simulation.run(rounds, &mut outputs);
+    } else {
+        if !outputs.is_empty() {
+            panic!("Temporal blocking can not be combined with outputs, use --framerate 0 and no other outputs");
         }
-        let round_timer = Instant::now();
-        gif_time += time_per_round;
-        while gif_time >= time_per_frame {
-            gif_time -= time_per_frame;
-            eprintln!("============================ Round {}", round);
-            images.push(draw_cells_detailed(grid_a).resized(
-                (WIDTH as f64 * image_scaling) as u32,
-                (height as f64 * image_scaling) as u32,
-                ril::ResizeAlgorithm::Lanczos3,
-            ));
+        if cli.in_place {
+            panic!(
+                "Temporal blocking needs the second grid, it can not be combined with --in-place"
+            );
         }
-        render_duration += round_timer.elapsed();
+        let blocking = TemporalBlocking::new(cli.tile_height, cli.temporal_blocking);
+        simulation.run_blocked(&blocking, rounds);
     }
+    drop(outputs);
 
-    let calculation_duration = core_duration + top_bottom_duration;
+    let timings = simulation.timings;
+    let core_duration = timings.core;
+    let top_bottom_duration = timings.top_bottom;
+    let communication_duration = timings.communication;
+    let render_duration = timings.render;
+    let calculation_duration = timings.calculation();
 
     let calculation_duration_per_cell = (calculation_duration.as_secs_f64() * 1000000000.0)
-        / (WIDTH * height * rounds * size as usize) as f64;
+        / (WIDTH * global_height * rounds) as f64;
     let top_bottom_duration_per_cell = (top_bottom_duration.as_secs_f64() * 1000000000.0)
         / (WIDTH * 2 * rounds * size as usize) as f64;
     let core_duration_per_cell = (core_duration.as_secs_f64() * 1000000000.0)
-        / (WIDTH * (height - 2) * rounds * size as usize) as f64;
+        / (WIDTH * (global_height - 2 * size as usize) * rounds) as f64;
 
     eprintln!(
         "Calculation duration per round: {}",
@@ -347,7 +521,7 @@ fn main() {
     );
 
     if rank == 0 {
-        println!(
+        let timings = format!(
             "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             WIDTH,
             height,
@@ -363,32 +537,56 @@ fn main() {
             communication_duration.as_secs_f64(),
             (calculation_duration + communication_duration).as_secs_f64(),
             render_duration.as_secs_f64(),
-            images.len()
+            renderer.as_ref().map_or(0, WebPRenderer::frames)
+                + video.as_ref().map_or(0, Y4mRenderer::frames)
+                + sender.as_ref().map_or(0, StripSender::frames)
         );
+        // The video on stdout must not be interrupted
+        if cli.y4m.as_ref().is_some_and(|path| path.as_os_str() == "-") {
+            eprintln!("{}", timings);
+        } else {
+            println!("{}", timings);
+        }
     }
 
-    if frames_per_second != 0 {
-        let mut output = ImageSequence::<Rgb>::new();
+    if let Some(ensemble) = &ensemble {
+        ensemble.write_average(
+            &simulation.coarse_fields(cli.block_size),
+            &cli.output_directory.join("ensemble.csv"),
+        );
+    }
 
-        // ImageSequence::open is lazy
-        for frame in images {
-            let mut frame = Frame::from_image(frame);
-            frame.set_delay(time_per_frame);
-            output.push_frame(frame);
-        }
+    if cli.collision_statistics {
+        Output::<WIDTH>::finish(&mut statistics);
+    }
 
-        eprintln!("Saving output to {}", filename);
+    if !cli.probe.is_empty() {
+        Output::<WIDTH>::finish(&mut probes);
+    }
 
-        let options = WebPEncoderOptions::new().with_lossless(true);
-        let f = File::create(&filename).expect("Create file");
-        let mut encoder =
-            WebPMuxEncoder::new(f, EncoderMetadata::from(&output).with_config(options))
-                .expect("new encoder");
-        for frame in output.iter() {
-            encoder.add_frame(frame).expect("adding frame");
+    if !simulation.obstacles().is_empty() {
+        Output::<WIDTH>::finish(&mut forces);
+        if rank == 0 {
+            for (body, (drag, lift)) in forces.mean().iter().enumerate() {
+                eprintln!("Mean drag and lift on body {}: {}, {}", body, drag, lift);
+            }
         }
-        encoder.finish().expect("finish");
-        // output.save_inferred(filename).unwrap();
     }
-    let _ = mpi_universe.as_ref().map_or(0, |o| o.0.world().rank());
+
+    if cli.tracers != 0 {
+        Output::<WIDTH>::finish(&mut tracers);
+        if let (0, Some(coefficient)) = (rank, tracers.diffusion_coefficient()) {
+            eprintln!("Self-diffusion coefficient: {}", coefficient);
+        }
+    }
+
+    if let Some(sender) = sender.as_mut() {
+        Output::<WIDTH>::finish(sender);
+    }
+    if let Some(video) = video.as_mut() {
+        Output::<WIDTH>::finish(video);
+    }
+    if let Some(renderer) = renderer.as_mut() {
+        renderer.save();
+    }
 }